@@ -0,0 +1,50 @@
+// lookup time of the router by the number of routes and the path length
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+#[allow(dead_code)]
+#[path = "../src/config/router.rs"]
+mod router;
+
+use router::{Pattern, Router};
+
+// "/svc{i}/v1/items", "/svc{i}/v1/items/{id}" and "/svc{i}/static/*"
+fn make_router(count: usize) -> Router<usize> {
+    let mut router = Router::new();
+    for i in 0..count / 3 + 1 {
+        for (path, prefix) in [
+            (format!("/svc{}/v1/items", i), false),
+            (format!("/svc{}/v1/items/{{id}}", i), false),
+            (format!("/svc{}/static/", i), true),
+        ] {
+            router.insert(&Pattern::parse(&path, prefix).unwrap(), 0, i);
+        }
+    }
+    router
+}
+
+fn bench_routes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("routes");
+    for count in [100, 1_000, 10_000] {
+        let router = make_router(count);
+        let path = format!("/svc{}/v1/items/42", count / 6);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &path, |b, path| {
+            b.iter(|| router.find(black_box(path), |_| true))
+        });
+    }
+    group.finish();
+}
+
+fn bench_path_length(c: &mut Criterion) {
+    let router = make_router(10_000);
+    let mut group = c.benchmark_group("path_length");
+    for depth in [1, 8, 64] {
+        let path = format!("/svc1000/static/{}", "a/".repeat(depth));
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &path, |b, path| {
+            b.iter(|| router.find(black_box(path), |_| true))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_routes, bench_path_length);
+criterion_main!(benches);
@@ -0,0 +1,146 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_address.rs"]
+mod test_address;
+
+use crate::tls::tls_connector::make_http_or_https_client;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request, Response};
+use hyper_rustls::HttpsConnector;
+use log::{info, warn};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::time;
+
+// an admin which doesn't answer in time is treated as down
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// base url of admin: scheme + host + port + base path (no trailing '/')
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminAddress(String);
+
+impl AdminAddress {
+    /// ip:port, host:port/base or http(s)://host:port/base
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let url = if s.contains("://") {
+            s.to_string()
+        } else {
+            format!("http://{}", s)
+        };
+        let uri = url.parse::<hyper::Uri>().map_err(|e| e.to_string())?;
+        match uri.scheme_str() {
+            Some("http") | Some("https") => {}
+            _ => return Err(format!("Invalid scheme of admin address: {}", s)),
+        }
+        if uri.host().is_none() || uri.port().is_none() {
+            return Err(format!(
+                "Admin address needs host and port (ex. 127.0.0.1:5581): {}",
+                s
+            ));
+        }
+        if uri.query().is_some() {
+            return Err(format!("Admin address can't have a query: {}", s));
+        }
+        Ok(AdminAddress(url.trim_end_matches('/').to_string()))
+    }
+
+    /// url of the admin api (path starts with '/')
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.0, path)
+    }
+}
+
+impl fmt::Display for AdminAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// comma separated admin addresses, tried in order
+pub fn parse_list(s: &str) -> Result<Vec<AdminAddress>, String> {
+    let addresses = s
+        .split(',')
+        .filter(|address| !address.trim().is_empty())
+        .map(AdminAddress::parse)
+        .collect::<Result<Vec<AdminAddress>, String>>()?;
+    if addresses.is_empty() {
+        return Err(String::from("Address of admin server is empty"));
+    }
+    Ok(addresses)
+}
+
+/// admin servers of the engine, failover to the next one when the current is down
+pub struct Admin {
+    addresses: Vec<AdminAddress>,
+    // index of the admin answered last
+    current: AtomicUsize,
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+}
+
+impl Admin {
+    pub fn new(addresses: Vec<AdminAddress>) -> Self {
+        assert!(!addresses.is_empty(), "no admin address");
+        Admin {
+            addresses,
+            current: AtomicUsize::new(0),
+            client: make_http_or_https_client(),
+        }
+    }
+
+    pub fn current(&self) -> &AdminAddress {
+        &self.addresses[self.current.load(Ordering::Relaxed)]
+    }
+
+    /// post json to the current admin, then the others if it's down (connection error, timeout, 5xx)
+    pub async fn post(&self, path: &str, body: String) -> Result<Response<Body>, String> {
+        let start = self.current.load(Ordering::Relaxed);
+        let mut last_error = String::new();
+
+        for i in 0..self.addresses.len() {
+            let index = (start + i) % self.addresses.len();
+            let address = &self.addresses[index];
+            match self.send(address, path, body.clone()).await {
+                Ok(resp) => {
+                    if index != start {
+                        info!("admin is changed to {}", address);
+                        self.current.store(index, Ordering::Relaxed);
+                    }
+                    return Ok(resp);
+                }
+                Err(e) => {
+                    if self.addresses.len() > 1 {
+                        warn!("admin {} is not available: {}", address, e);
+                    }
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn send(
+        &self,
+        address: &AdminAddress,
+        path: &str,
+        body: String,
+    ) -> Result<Response<Body>, String> {
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(address.url(path))
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .map_err(|e| e.to_string())?;
+
+        let resp = match time::timeout(REQUEST_TIMEOUT, self.client.request(req)).await {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(e)) => return Err(e.to_string()),
+            Err(_) => return Err(String::from("timed out")),
+        };
+        if resp.status().is_server_error() {
+            return Err(format!("Not 200 OK(status code:{})", resp.status()));
+        }
+        Ok(resp)
+    }
+}
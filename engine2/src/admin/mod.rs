@@ -1,2 +1,4 @@
+pub mod address;
 pub mod poll;
 pub mod register;
+pub mod snapshot;
@@ -1,14 +1,42 @@
-use crate::admin::register::RegisterResponse;
-use crate::config::api;
-use crate::{config, monitor};
-use hyper::client::HttpConnector;
-use hyper::{body, Body, Client, Method, Request, StatusCode};
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_poll.rs"]
+mod test_poll;
+
+use super::address::Admin;
+use super::register;
+use super::snapshot;
+use crate::config::validate::{self, ApiReject};
+use crate::config::{api, system};
+use crate::logger::access::{self, AccessLogDrops};
+use crate::logger::time::DateTime;
+use crate::monitor;
+use crate::service::concurrency;
+use crate::service::rate_limit::ClusterCounter;
+use crate::service::rate_limit_sync;
+use crate::service::size_limit::{self, SizeRejections};
+use hyper::{body, StatusCode};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use monitor::latency::{self, ApiLatency, TakenLatencies};
+use monitor::stats::{self, Traffic};
 use monitor::system::{get_cpu_usage, get_memory_usage, get_network_usage};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, RwLock};
 use std::time::SystemTime;
 use std::time::{Duration, UNIX_EPOCH};
 use tokio::{task, time};
 
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+// max delay of the polls failing in a row
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref CONNECTION: RwLock<Option<AdminConnection>> = RwLock::new(None);
+    static ref LAST_POLL: RwLock<Option<PollResult>> = RwLock::new(None);
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct PollRequest {
@@ -22,10 +50,21 @@ struct PollRequest {
     client_count: usize,
     request_count: usize,
     response_count: usize,
+    // average milliseconds of the responses since the last poll
     response_time: usize,
+    // 1xx, 2xx, 3xx, 4xx, 5xx
     response_status: Vec<usize>,
     active_requests: Vec<ActiveRequestInfo>,
     error_message: String,
+    rate_limit_counters: Vec<ClusterCounter>,
+    shed_count: usize,
+    size_rejections: SizeRejections,
+    access_log_drops: AccessLogDrops,
+    api_latencies: Vec<ApiLatency>,
+    // revision of the api map applied (ack of "api" and "apiDelta")
+    api_revision: u64,
+    // apis not applied because of the errors
+    api_rejects: Vec<ApiReject>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -33,6 +72,7 @@ struct PollRequest {
 struct ActiveRequestInfo {
     api_name: String,
     api_version: usize,
+    // milliseconds
     elapsed_time: usize,
 }
 
@@ -41,21 +81,206 @@ struct PollResponse {
     action: String,
     #[serde(default)]
     api: Vec<api::DeserializedApi>,
+    #[serde(default, rename = "rateLimitCounters")]
+    rate_limit_counters: Vec<ClusterCounter>,
+    #[serde(default)]
+    config: Option<system::SystemConfig>,
+    // revision of "api" (the whole apis)
+    #[serde(default)]
+    revision: Option<u64>,
+    // changes of "apiDelta"
+    #[serde(default)]
+    delta: Option<api::ApiDelta>,
+}
+
+#[derive(Debug)]
+enum PollError {
+    // admin doesn't know the engine id (404, 401)
+    Unregistered(StatusCode),
+    Failed(String),
+}
+
+impl fmt::Display for PollError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PollError::Unregistered(status) => write!(f, "engine is not registered ({})", status),
+            PollError::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+/// state of the connection to admin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConnectionState {
+    Connected,
+    Disconnected,
+    Registering,
 }
 
-pub fn handle(client: Client<HttpConnector>, id: String) {
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminConnection {
+    pub state: ConnectionState,
+    pub address: String,
+    /// polls (or registers) failed in a row
+    pub consecutive_failures: u32,
+    /// time of the last state change
+    pub since: String,
+}
+
+// doubled for each failure in a row
+fn backoff(failures: u32) -> Duration {
+    if failures == 0 {
+        return POLL_INTERVAL;
+    }
+    POLL_INTERVAL
+        .saturating_mul(2u32.saturating_pow(failures - 1))
+        .min(MAX_BACKOFF)
+}
+
+/// delay to the next poll, a random half to all of the backoff after failures
+/// (the engines don't retry the admin at the same time)
+pub fn next_poll_delay(failures: u32) -> Duration {
+    let backoff = backoff(failures);
+    if failures == 0 {
+        return backoff;
+    }
+    let mut random = [0u8; 4];
+    getrandom::getrandom(&mut random).expect("no random source");
+    let ratio = f64::from(u32::from_le_bytes(random)) / f64::from(u32::MAX);
+    backoff.div_f64(2.0).mul_f64(1.0 + ratio)
+}
+
+fn set_connection(admin: &Admin, state: ConnectionState, failures: u32) {
+    let mut connection = CONNECTION.write().unwrap();
+    let since = match connection.as_ref() {
+        Some(old) if old.state == state => old.since.clone(),
+        old => {
+            match (old.map(|old| old.state), state) {
+                (None, _) => {}
+                (_, ConnectionState::Connected) => info!("connected to admin {}", admin.current()),
+                (_, ConnectionState::Disconnected) => warn!("disconnected from admin"),
+                (_, ConnectionState::Registering) => info!("registering to admin again"),
+            }
+            DateTime::from(SystemTime::now()).to_iso8601()
+        }
+    };
+    *connection = Some(AdminConnection {
+        state,
+        address: admin.current().to_string(),
+        consecutive_failures: failures,
+        since,
+    });
+}
+
+/// None before register
+pub fn get_connection() -> Option<AdminConnection> {
+    CONNECTION.read().unwrap().clone()
+}
+
+/// poll to admin until the engine stops
+/// (id None: not registered yet, register with the message first)
+pub fn handle(admin: Arc<Admin>, id: Option<String>, register_message: String) {
     task::spawn(async move {
-        // interval
-        let mut interval = time::interval(Duration::from_secs(5));
+        let mut id = id;
+        let mut failures = 0;
+        let state = match id {
+            Some(_) => ConnectionState::Connected,
+            None => ConnectionState::Registering,
+        };
+        set_connection(&admin, state, failures);
         loop {
-            interval.tick().await;
+            time::sleep(next_poll_delay(failures)).await;
+
+            if let Some(current_id) = id.clone() {
+                let (message, taken) = make_poll_message(current_id.clone());
+                let result = send_poll_msg(message, taken, &admin).await;
+                record_poll_result(&result);
+                match result {
+                    Ok(_) => {
+                        failures = 0;
+                        set_connection(&admin, ConnectionState::Connected, failures);
+                        continue;
+                    }
+                    Err(PollError::Unregistered(status)) => {
+                        warn!(
+                            "admin doesn't know the engine {} ({}), register again",
+                            current_id, status
+                        );
+                        id = None;
+                    }
+                    Err(PollError::Failed(e)) => {
+                        failures += 1;
+                        warn!(
+                            "failed to poll ({} in a row): {}, retry in {:?}",
+                            failures,
+                            e,
+                            next_poll_delay(failures)
+                        );
+                        set_connection(&admin, ConnectionState::Disconnected, failures);
+                        continue;
+                    }
+                }
+            }
 
-            let message = make_poll_message(id.clone());
-            send_poll_msg(message, client.clone()).await.unwrap();
+            // not registered: admin was unreachable at boot or forgot the engine
+            set_connection(&admin, ConnectionState::Registering, failures);
+            match register::register(&admin, &register_message).await {
+                Ok(new_id) => {
+                    id = Some(new_id);
+                    failures = 0;
+                    set_connection(&admin, ConnectionState::Connected, failures);
+                }
+                Err(e) => {
+                    failures += 1;
+                    warn!(
+                        "failed to register: {}, retry in {:?}",
+                        e,
+                        next_poll_delay(failures)
+                    );
+                    set_connection(&admin, ConnectionState::Registering, failures);
+                }
+            }
         }
     });
 }
 
+/// result of the last poll to admin
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PollResult {
+    pub time: String,
+    pub success: bool,
+    /// action of the response (empty if admin sent nothing)
+    pub action: String,
+    pub error: Option<String>,
+}
+
+fn record_poll_result(result: &Result<String, PollError>) {
+    let time = DateTime::from(SystemTime::now()).to_iso8601();
+    let poll_result = match result {
+        Ok(action) => PollResult {
+            time,
+            success: true,
+            action: action.clone(),
+            error: None,
+        },
+        Err(e) => PollResult {
+            time,
+            success: false,
+            action: String::new(),
+            error: Some(e.to_string()),
+        },
+    };
+    *LAST_POLL.write().unwrap() = Some(poll_result);
+}
+
+/// None before the first poll
+pub fn get_last_poll() -> Option<PollResult> {
+    LAST_POLL.read().unwrap().clone()
+}
+
 struct MonitoringInfo {
     memory_usage: u64,
     memory_usage_total: u64,
@@ -88,7 +313,26 @@ fn get_monitoring_info() -> MonitoringInfo {
     }
 }
 
-fn make_poll_message(id: String) -> String {
+// statistics taken for the poll, put back if admin didn't receive them
+struct TakenStats {
+    traffic: Traffic,
+    shed_count: usize,
+    size_rejections: SizeRejections,
+    access_log_drops: AccessLogDrops,
+    latencies: TakenLatencies,
+}
+
+impl TakenStats {
+    fn requeue(self) {
+        stats::requeue_traffic(&self.traffic);
+        concurrency::requeue_shed_count(self.shed_count);
+        size_limit::requeue_rejections(&self.size_rejections);
+        access::requeue_drops(&self.access_log_drops);
+        latency::requeue_latencies(self.latencies);
+    }
+}
+
+fn make_poll_message(id: String) -> (String, TakenStats) {
     // get the information needed to make a poll message
     let sys_time = match SystemTime::now().duration_since(UNIX_EPOCH) {
         Ok(n) => n.as_millis(),
@@ -97,62 +341,138 @@ fn make_poll_message(id: String) -> String {
         }
     };
     let monitoring_info = get_monitoring_info();
+    let taken = TakenStats {
+        traffic: stats::take_traffic(),
+        shed_count: concurrency::take_shed_count(),
+        size_rejections: size_limit::take_rejections(),
+        access_log_drops: access::take_drops(),
+        latencies: latency::take_latencies(),
+    };
+    let traffic = &taken.traffic;
+    let active_requests = stats::active_requests()
+        .into_iter()
+        .map(|request| ActiveRequestInfo {
+            api_name: request.api_name,
+            api_version: request.api_version,
+            elapsed_time: request.elapsed.as_millis() as usize,
+        })
+        .collect();
+
+    let api_rejects = validate::get_rejects();
+    let error_message = if api_rejects.is_empty() {
+        String::new()
+    } else {
+        format!("{} apis are rejected", api_rejects.len())
+    };
 
     let message = PollRequest {
-        id: id,
+        id,
         time: sys_time,
         total_memory: monitoring_info.memory_usage_total,
         used_memory: monitoring_info.memory_usage,
         used_cpu: monitoring_info.cpu_usage,
         used_network_traffic_in: monitoring_info.network_usage_in,
         used_network_traffic_out: monitoring_info.network_usage_out,
-        client_count: 0,
-        request_count: 0,
-        response_count: 0,
-        response_time: 0,
-        response_status: vec![0, 0, 0, 0, 0],
-        active_requests: vec![],
-        error_message: String::from(""),
+        client_count: traffic.client_count,
+        request_count: traffic.request_count,
+        response_count: traffic.response_count,
+        response_time: traffic.response_time,
+        response_status: traffic.response_status.clone(),
+        active_requests,
+        error_message,
+        rate_limit_counters: rate_limit_sync::take_counters_for_admin(),
+        shed_count: taken.shed_count,
+        size_rejections: taken.size_rejections.clone(),
+        access_log_drops: taken.access_log_drops.clone(),
+        api_latencies: taken.latencies.report(),
+        api_revision: api::get_revision(),
+        api_rejects,
     };
 
-    serde_json::to_string(&message).unwrap()
+    (serde_json::to_string(&message).unwrap(), taken)
 }
 
-async fn send_poll_msg(body: String, client: Client<HttpConnector>) -> Result<(), String> {
-    let req = Request::builder()
-        .method(Method::POST)
-        .uri("http://118.67.135.216:5581/poll")
-        .header("content-type", "application/json")
-        .body(Body::from(body))
-        .unwrap();
-
-    let resp = match client.request(req).await {
-        Ok(resp) => resp,
-        Err(e) => return Err(e.message().to_string()),
+// action of the response
+async fn send_poll_msg(
+    body: String,
+    taken: TakenStats,
+    admin: &Admin,
+) -> Result<String, PollError> {
+    let resp = match admin.post("/poll", body).await {
+        Ok(resp) if resp.status() == StatusCode::OK => resp,
+        result => {
+            // the rate limit counters and the statistics are sent again with the next poll
+            rate_limit_sync::requeue_counters_for_admin();
+            taken.requeue();
+            return match result {
+                Ok(resp) => match resp.status() {
+                    StatusCode::NOT_FOUND | StatusCode::UNAUTHORIZED => {
+                        Err(PollError::Unregistered(resp.status()))
+                    }
+                    status => Err(PollError::Failed(format!(
+                        "Not 200 OK(status code:{})",
+                        status
+                    ))),
+                },
+                Err(e) => Err(PollError::Failed(e)),
+            };
+        }
     };
 
-    if resp.status() != StatusCode::OK {
-        return Err(format!("Not 200 OK(status code:{}", resp.status()));
-    }
-
-    let body_bytes = body::to_bytes(resp.into_body()).await.unwrap();
-    if !body_bytes.is_empty() {
-        let info: PollResponse = serde_json::from_slice(&body_bytes.to_vec()).unwrap();
-        process_admin_message(info);
+    let info = body::to_bytes(resp.into_body())
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|body_bytes| {
+            if body_bytes.is_empty() {
+                return Ok(None);
+            }
+            serde_json::from_slice::<PollResponse>(&body_bytes)
+                .map(Some)
+                .map_err(|e| format!("invalid poll response: {}", e))
+        });
+    match info {
+        Ok(Some(info)) => {
+            let action = info.action.clone();
+            process_admin_message(info);
+            Ok(action)
+        }
+        result => {
+            // admin received the rate limit counters, without the totals
+            rate_limit_sync::apply_counters_from_admin(vec![]);
+            result.map(|_| String::new()).map_err(PollError::Failed)
+        }
     }
-
-    Ok(())
 }
 
-///! ToDo: add process by "action"
+/// ToDo: add process by "action"
 fn process_admin_message(info: PollResponse) {
-    if info.action.eq("api") {
-        api::insert_apis_into_new_map(info.api);
-    } else if info.action.eq("config") {
-        // ToDo: 없어짐 ^^
-    } else if info.action.eq("shutdown") {
-        // ToDo:
-    } else if info.action.eq("restart") {
-        // ToDo:
+    // total hits of cluster-wide rate limits in the group
+    rate_limit_sync::apply_counters_from_admin(info.rate_limit_counters);
+
+    match info.action.as_str() {
+        "api" => {
+            let revision = info.revision.unwrap_or_default();
+            snapshot::update_apis(&info.api, revision);
+            api::insert_apis_into_new_map(info.api, revision);
+        }
+        "apiDelta" => {
+            if let Some(delta) = info.delta {
+                // admin sends the whole apis if the ack is not the revision
+                match api::apply_delta(&delta) {
+                    Ok(()) => snapshot::apply_delta(&delta),
+                    Err(e) => warn!("api delta is not applied: {}", e),
+                }
+            }
+        }
+        "config" => {
+            if let Some(config) = info.config {
+                snapshot::update_config(&config);
+                system::apply(&config);
+            }
+        }
+        "shutdown" | "restart" => {
+            // ToDo:
+        }
+        _ => {}
     }
 }
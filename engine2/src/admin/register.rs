@@ -1,9 +1,27 @@
+use super::address::Admin;
 use super::poll;
+use super::snapshot;
 use crate::config::{api, args, system};
 use crate::monitor;
-use hyper::{body, Body, Client, Method, Request, StatusCode};
+use hyper::{body, StatusCode};
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
 use monitor::system::{get_hostname, get_logical_cpus};
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+/// protocol version of the engine sent to admin
+pub const ENGINE_VERSION: &str = "2.1";
+
+lazy_static! {
+    // id given by admin on register
+    static ref ENGINE_ID: RwLock<String> = RwLock::new(String::new());
+}
+
+/// id given by admin (empty before register)
+pub fn get_engine_id() -> String {
+    ENGINE_ID.read().unwrap().clone()
+}
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -17,48 +35,69 @@ struct RegisterRequest {
     error_message: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct RegisterResponse {
     pub api: Vec<api::DeserializedApi>,
     pub config: system::SystemConfig,
     pub id: String,
+    /// revision of the apis (0: admin doesn't send the deltas)
+    #[serde(default)]
+    pub revision: u64,
 }
 
 pub async fn handle(config: args::SystemConfig) -> Result<(), String> {
-    let uri = format!("http://{}/register", config.admin_address);
+    // register and poll share the admin (and its failover)
+    let admin = Arc::new(Admin::new(config.admin_address.clone()));
 
     let message = make_register_message(config);
+    let id = match register(&admin, &message).await {
+        Ok(id) => Some(id),
+        Err(e) => {
+            // keep serving with the last known config, register in the background
+            let snapshot = match snapshot::load() {
+                Some(Ok(snapshot)) => snapshot,
+                Some(Err(snapshot_error)) => {
+                    return Err(format!("{} (snapshot: {})", e, snapshot_error))
+                }
+                None => return Err(e),
+            };
+            warn!(
+                "failed to register: {}, boot from the snapshot saved at {}",
+                e, snapshot.saved_at
+            );
+            process_register_response_message(snapshot.register);
+            None
+        }
+    };
+
+    // start to poll to admin every 5 seconds (register again with the message if admin forgets the engine)
+    poll::handle(admin, id, message);
 
+    Ok(())
+}
+
+/// register the engine to admin, apply the response and return the engine id
+pub async fn register(admin: &Admin, message: &str) -> Result<String, String> {
     // 1. connect and send register msg to admin
-    let req = Request::builder()
-        .method(Method::POST)
-        .uri(uri)
-        .header("content-type", "application/json")
-        .body(Body::from(message))
-        .unwrap();
-
-    let client = Client::new();
-
-    let resp = match client.request(req).await {
-        Ok(resp) => resp,
-        Err(e) => return Err(e.message().to_string()),
-    };
+    let resp = admin.post("/register", message.to_string()).await?;
 
     if resp.status() != StatusCode::OK {
         return Err(format!("Not 200 OK(status code:{})", resp.status()));
     }
 
-    let body_bytes = body::to_bytes(resp.into_body()).await.unwrap();
-    let info: RegisterResponse = serde_json::from_slice(&body_bytes.to_vec()).unwrap();
+    let body_bytes = body::to_bytes(resp.into_body())
+        .await
+        .map_err(|e| e.to_string())?;
+    let info: RegisterResponse = serde_json::from_slice(&body_bytes)
+        .map_err(|e| format!("invalid register response: {}", e))?;
 
     // 2. process admin's register's response message
-    let id = info.id.clone(); // todo: global variable1
+    let id = info.id.clone();
+    snapshot::save(&info);
     process_register_response_message(info);
 
-    // 3. start to poll to admin every 5 seconds
-    poll::handle(client, id);
-
-    Ok(())
+    info!("registered to {} as {}", admin.current(), id);
+    Ok(id)
 }
 
 fn make_register_message(config: args::SystemConfig) -> String {
@@ -72,7 +111,7 @@ fn make_register_message(config: args::SystemConfig) -> String {
         engine_name,
         group_name,
         host_name,
-        version: String::from("2.1"),
+        version: String::from(ENGINE_VERSION),
         cpu: cpus.to_string(),
         error_message: String::from(""),
     };
@@ -97,11 +136,20 @@ fn get_system_info() -> (String, usize) {
 }
 
 fn process_register_response_message(info: RegisterResponse) {
-    // todo: global variable!
-
     // 1. info.id
+    *ENGINE_ID.write().unwrap() = info.id.clone();
+
     // 2. info.config
+    debug!(
+        "registered as {}: {} apis, http port {}, log level {:?}, access log format {:?}",
+        info.id,
+        info.api.len(),
+        info.config.listen_http_port,
+        info.config.system_log_level,
+        info.config.access_log_format
+    );
+    system::apply(&info.config);
 
     // 3. info.api
-    api::insert_apis_into_new_map(info.api);
+    api::insert_apis_into_new_map(info.api, info.revision);
 }
@@ -0,0 +1,141 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_snapshot.rs"]
+mod test_snapshot;
+
+use super::register::RegisterResponse;
+use crate::config::{api, system};
+use crate::logger::time::DateTime;
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+lazy_static! {
+    // None: snapshot is disabled
+    static ref SNAPSHOT_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+    // the last state of admin, apis and config are updated by poll
+    static ref LAST: Mutex<Option<RegisterResponse>> = Mutex::new(None);
+    // sequence of the last snapshot written to the file
+    static ref WRITTEN: Mutex<u64> = Mutex::new(0);
+}
+
+// sequence of the last snapshot serialized
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// last known config of admin, used to boot when admin is unreachable
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub saved_at: String,
+    pub register: RegisterResponse,
+}
+
+/// file to keep the snapshot (None: disabled)
+pub fn set_path(path: Option<String>) {
+    *SNAPSHOT_PATH.lock().unwrap() = path.map(PathBuf::from);
+}
+
+fn get_path() -> Option<PathBuf> {
+    SNAPSHOT_PATH.lock().unwrap().clone()
+}
+
+/// keep the response of register (overwrites the previous one)
+pub fn save(response: &RegisterResponse) {
+    let mut last = LAST.lock().unwrap();
+    *last = Some(response.clone());
+    write_last(&last);
+}
+
+/// apis are replaced by poll
+pub fn update_apis(apis: &[api::DeserializedApi], revision: u64) {
+    let mut last = LAST.lock().unwrap();
+    if let Some(response) = last.as_mut() {
+        response.api = apis.to_vec();
+        response.revision = revision;
+    }
+    write_last(&last);
+}
+
+/// apis are changed by the delta of poll
+pub fn apply_delta(delta: &api::ApiDelta) {
+    let mut last = LAST.lock().unwrap();
+    if let Some(response) = last.as_mut() {
+        delta.apply_to(&mut response.api);
+        response.revision = delta.revision;
+    }
+    write_last(&last);
+}
+
+/// config is changed by poll
+pub fn update_config(config: &system::SystemConfig) {
+    let mut last = LAST.lock().unwrap();
+    if let Some(response) = last.as_mut() {
+        response.config = config.clone();
+    }
+    write_last(&last);
+}
+
+// serialized under the lock of LAST, written by a blocking thread
+fn write_last(last: &Option<RegisterResponse>) {
+    let (path, response) = match (get_path(), last) {
+        (Some(path), Some(response)) => (path, response),
+        _ => return,
+    };
+    let snapshot = Snapshot {
+        saved_at: DateTime::from(SystemTime::now()).to_iso8601(),
+        register: response.clone(),
+    };
+    let data = match serde_json::to_vec(&snapshot) {
+        Ok(data) => data,
+        Err(e) => return warn!("failed to serialize snapshot: {}", e),
+    };
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed) + 1;
+    tokio::task::spawn_blocking(move || {
+        // a newer snapshot may be written first
+        let mut written = WRITTEN.lock().unwrap();
+        if *written > sequence {
+            return;
+        }
+        match write(&path, &data) {
+            Ok(()) => debug!("snapshot is saved to {}", path.display()),
+            Err(e) => warn!("failed to save snapshot to {}: {}", path.display(), e),
+        }
+        *written = sequence;
+    });
+}
+
+// write to a temporary file and rename, the old snapshot stays if it fails
+pub fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // the config has the private key of https
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&temp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&temp, path)
+}
+
+pub fn read(path: &Path) -> Result<Snapshot, String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&data).map_err(|e| format!("invalid snapshot: {}", e))
+}
+
+/// the saved snapshot (None: disabled)
+pub fn load() -> Option<Result<Snapshot, String>> {
+    get_path().map(|path| read(&path))
+}
@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod test_address {
+    use super::super::*;
+
+    #[test]
+    fn test_parse_address() {
+        let address = AdminAddress::parse("127.0.0.1:5581").unwrap();
+        assert_eq!(address.url("/poll"), "http://127.0.0.1:5581/poll");
+
+        let address = AdminAddress::parse("https://admin.example.com:8443/osori/").unwrap();
+        assert_eq!(
+            address.url("/register"),
+            "https://admin.example.com:8443/osori/register"
+        );
+
+        let address = AdminAddress::parse("admin:5581/api/v2").unwrap();
+        assert_eq!(address.url("/poll"), "http://admin:5581/api/v2/poll");
+
+        assert!(AdminAddress::parse("127.0.0.1").is_err());
+        assert!(AdminAddress::parse("ftp://127.0.0.1:21").is_err());
+        assert!(AdminAddress::parse("127.0.0.1:5581/?a=1").is_err());
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let addresses = parse_list("10.0.0.1:5581, 10.0.0.2:5581/osori").unwrap();
+        assert_eq!(
+            addresses,
+            vec![
+                AdminAddress::parse("10.0.0.1:5581").unwrap(),
+                AdminAddress::parse("10.0.0.2:5581/osori").unwrap(),
+            ]
+        );
+        assert!(parse_list("").is_err());
+        assert!(parse_list("10.0.0.1:5581,10.0.0.2").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failover() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // the second admin answers 200, nothing listens on port 1
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                    .await;
+            }
+        });
+
+        let admin = Admin::new(parse_list(&format!("127.0.0.1:1,127.0.0.1:{}", port)).unwrap());
+        let resp = admin.post("/poll", String::new()).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            admin.current().url(""),
+            format!("http://127.0.0.1:{}", port)
+        );
+    }
+}
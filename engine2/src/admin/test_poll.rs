@@ -0,0 +1,26 @@
+#[cfg(test)]
+mod test_poll {
+    use super::super::*;
+
+    #[test]
+    fn test_backoff() {
+        let secs = |failures| backoff(failures).as_secs();
+        assert_eq!(secs(0), 5);
+        assert_eq!(secs(1), 5);
+        assert_eq!(secs(2), 10);
+        assert_eq!(secs(3), 20);
+        assert_eq!(secs(4), 40);
+        assert_eq!(secs(5), 60);
+        assert_eq!(secs(100), 60);
+    }
+
+    #[test]
+    fn test_next_poll_delay() {
+        assert_eq!(next_poll_delay(0), POLL_INTERVAL);
+        for failures in [1, 3, 100] {
+            let delay = next_poll_delay(failures);
+            assert!(delay >= backoff(failures) / 2, "{:?}", delay);
+            assert!(delay <= backoff(failures), "{:?}", delay);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+#[cfg(test)]
+mod test_snapshot {
+    use super::super::*;
+    use crate::config::test_fixture::make_config;
+
+    #[test]
+    fn test_write_and_read() {
+        let snapshot = Snapshot {
+            saved_at: String::from("2022-05-01T00:00:00.000Z"),
+            register: RegisterResponse {
+                api: vec![],
+                config: make_config(""),
+                id: String::from("engine-1"),
+                revision: 3,
+            },
+        };
+
+        let path = std::env::temp_dir().join(format!("osori-snapshot-{}.json", std::process::id()));
+        write(&path, &serde_json::to_vec(&snapshot).unwrap()).unwrap();
+        let loaded = read(&path).unwrap();
+        assert_eq!(loaded.saved_at, snapshot.saved_at);
+        assert_eq!(loaded.register.id, "engine-1");
+        assert_eq!(loaded.register.revision, 3);
+        assert_eq!(loaded.register.config.access_log_format, "combined");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_file(&path).unwrap();
+
+        assert!(read(&path).is_err());
+    }
+}
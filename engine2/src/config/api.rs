@@ -1,26 +1,31 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_api.rs"]
+mod test_api;
+
+use super::ip_filter::{IpFilter, IpRules};
+use super::limit::{ConcurrencyLimit, RateLimit};
+use super::matcher::{self, RouteRequest, ValueMatch};
+use super::router::{make_target_path, Pattern, Router};
+use super::validate::{self, RouteKey};
+use arc_swap::ArcSwap;
 use lazy_static::lazy_static;
+use log::{debug, info, trace, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-// for global api map
+// for global api map: readers load the current map, writers publish a new one
 lazy_static! {
-    static ref GLOBAL_API_VIEW: AtomicUsize = AtomicUsize::new(0);
-    static ref GLOBAL_API_MAP_LEFT: RwLock<Map> = RwLock::new(Map::new());
-    static ref GLOBAL_API_MAP_RIGHT: RwLock<Map> = RwLock::new(Map::new());
+    static ref GLOBAL_API_MAP: ArcSwap<Map> = ArcSwap::from_pointee(Map::new());
+    // one writer at a time, the changes are not lost
+    static ref GLOBAL_API_MAP_WRITER: Mutex<()> = Mutex::new(());
 }
 
-// atomic operation for gloval view/map
-fn get_gloval_view() -> usize {
-    GLOBAL_API_VIEW.load(Ordering::SeqCst) & 1
-}
-
-// change the global view for api map
-fn change_global_view() {
-    GLOBAL_API_VIEW.fetch_add(1, Ordering::SeqCst);
-}
+// changed whenever a map is published (the state of the apis can be cleaned up)
+static GLOBAL_API_MAP_GENERATION: AtomicU64 = AtomicU64::new(0);
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -40,187 +45,489 @@ pub struct DeserializedApi {
     pub target_path: String,
     pub target_servers: Vec<String>,
     pub version: usize,
+    #[serde(default)]
+    pub rate_limits: Vec<RateLimit>,
+    #[serde(default)]
+    pub concurrency_limit: Option<ConcurrencyLimit>,
+    #[serde(default)]
+    pub ip_filter: Option<IpFilter>,
+    /// bytes of the request body (None: unlimited)
+    #[serde(default)]
+    pub max_body_size: Option<u64>,
+    /// hosts of the request ("*.example.com": any subdomain, empty: any host)
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// headers the request must have (all of them)
+    #[serde(default)]
+    pub headers: Vec<ValueMatch>,
+    /// query parameters the request must have (all of them)
+    #[serde(default)]
+    pub query: Vec<ValueMatch>,
+}
+
+// exact host: 2, wildcard host ("*.example.com"): 1
+fn host_rank_of(pattern: &str) -> usize {
+    if pattern.starts_with('*') {
+        1
+    } else {
+        2
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ManagedApi {
     pub match_prefix: bool,
+    pub ip_rules: Option<IpRules>,
     pub de_api: DeserializedApi,
 }
 
 impl ManagedApi {
     pub fn new(de_api: DeserializedApi) -> Self {
+        let ip_rules = de_api.ip_filter.as_ref().map(|filter| {
+            IpRules::parse(filter).unwrap_or_else(|e| {
+                // fail closed: deny all if the filter is broken
+                warn!("ip filter of {} is ignored, deny all: {}", de_api.name, e);
+                IpRules {
+                    allow: vec![],
+                    deny: vec!["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()],
+                }
+            })
+        });
         let mut m_api = ManagedApi {
             match_prefix: false,
+            ip_rules,
             de_api,
         };
         m_api.fix_matchtype_and_remove_asterisk();
         m_api
     }
 
-    pub fn get_key(&self) -> String {
-        self.de_api.base_path.clone()
+    /// pattern of the base path for the router
+    pub fn pattern(&self) -> Result<Pattern, String> {
+        Pattern::parse(&self.de_api.base_path, self.match_prefix)
+    }
+
+    /// the request has the method, version, headers and query parameters of the api,
+    /// and a host of the rank (see `host_rank`)
+    /// (without the version: the latest, or any version if no api of the path is the latest)
+    pub fn matches(&self, req: &RouteRequest, host_rank: usize, path_has_latest: bool) -> bool {
+        let de_api = &self.de_api;
+        de_api.methods.iter().any(|m| m == req.method)
+            && match req.version {
+                Some(version) => version == de_api.version,
+                // not an older version if the latest doesn't match
+                None => de_api.latest_version || !path_has_latest,
+            }
+            && (de_api.hosts.is_empty()
+                || req.host.as_ref().is_some_and(|host| {
+                    de_api.hosts.iter().any(|pattern| {
+                        host_rank_of(pattern) == host_rank && matcher::host_matches(pattern, host)
+                    })
+                }))
+            && de_api
+                .headers
+                .iter()
+                .all(|rule| matcher::header_matches(rule, req.headers))
+            && de_api
+                .query
+                .iter()
+                .all(|rule| matcher::query_matches(rule, req.query))
+    }
+
+    /// ranks of the hosts, the api is routed once for each of them
+    pub fn host_ranks(&self) -> Vec<usize> {
+        let mut ranks: Vec<usize> = self
+            .de_api
+            .hosts
+            .iter()
+            .map(|host| host_rank_of(host))
+            .collect();
+        ranks.sort_unstable_by(|a, b| b.cmp(a));
+        ranks.dedup();
+        if ranks.is_empty() {
+            ranks.push(0);
+        }
+        ranks
+    }
+
+    /// the apis of the same path are tried in the order:
+    /// the matched host is exact > wildcard > any host, then more headers and query parameters,
+    /// then the latest version, then the higher version (serve the requests without the version)
+    pub fn priority(&self, host_rank: usize) -> usize {
+        let de_api = &self.de_api;
+        let conditions = host_rank * 1024 + de_api.headers.len() + de_api.query.len();
+        let version = de_api.version.min(u32::MAX as usize);
+        ((conditions * 2 + de_api.latest_version as usize) << 32) + version
     }
 
     fn fix_matchtype_and_remove_asterisk(&mut self) {
-        // fix match type
-        let mut len = self.de_api.base_path.len();
-        let mut path = self.de_api.base_path.as_bytes();
-        if path[len - 1] == b'*' {
+        // fix match type (empty paths are rejected by validate, never panic here)
+        if let Some(base_path) = self.de_api.base_path.strip_suffix('*') {
             // set
             self.match_prefix = true;
             // remove '*' in base_path
-            self.de_api.base_path = self.de_api.base_path[0..len - 1].to_string();
+            self.de_api.base_path = base_path.to_string();
 
             // ToDo: how to process '*'?
-            len = self.de_api.target_path.len();
-            path = self.de_api.target_path.as_bytes();
-            if path[len - 1] == b'*' {
+            if let Some(target_path) = self.de_api.target_path.strip_suffix('*') {
                 // remove '*' in target_path
-                self.de_api.target_path = self.de_api.target_path[0..len - 1].to_string();
+                self.de_api.target_path = target_path.to_string();
             }
         }
     }
 }
 
+/// api to delete from the map
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApiRef {
+    pub id: String,
+    pub version: usize,
+}
+
+/// changes of the apis from the base revision of admin
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiDelta {
+    pub base_revision: u64,
+    pub revision: u64,
+    /// added or updated (same id and version)
+    #[serde(default)]
+    pub upsert: Vec<DeserializedApi>,
+    #[serde(default)]
+    pub delete: Vec<ApiRef>,
+}
+
+impl ApiDelta {
+    /// apply to the list of the apis (ex. snapshot)
+    pub fn apply_to(&self, apis: &mut Vec<DeserializedApi>) {
+        apis.retain(|api| {
+            !self
+                .delete
+                .iter()
+                .any(|r| r.id == api._id && r.version == api.version)
+                && !self
+                    .upsert
+                    .iter()
+                    .any(|new| new._id == api._id && new.version == api.version)
+        });
+        apis.extend(self.upsert.iter().cloned());
+    }
+}
+
+/// api in the map without the api keys
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiSummary {
+    pub id: String,
+    pub name: String,
+    pub version: usize,
+    pub latest_version: bool,
+    pub methods: Vec<String>,
+    pub base_path: String,
+    pub target_path: String,
+    pub target_servers: Vec<String>,
+    pub match_prefix: bool,
+    pub hosts: Vec<String>,
+    pub auth_type: String,
+    pub api_key_count: usize,
+}
+
+impl From<&ManagedApi> for ApiSummary {
+    fn from(m_api: &ManagedApi) -> Self {
+        let de_api = &m_api.de_api;
+        ApiSummary {
+            id: de_api._id.clone(),
+            name: de_api.name.clone(),
+            version: de_api.version,
+            latest_version: de_api.latest_version,
+            methods: de_api.methods.clone(),
+            base_path: de_api.base_path.clone(),
+            target_path: de_api.target_path.clone(),
+            target_servers: de_api.target_servers.clone(),
+            match_prefix: m_api.match_prefix,
+            hosts: de_api.hosts.clone(),
+            auth_type: de_api.auth_type.clone(),
+            api_key_count: de_api.api_keys.len(),
+        }
+    }
+}
+
+/// the global api map
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapDump {
+    pub revision: u64,
+    pub exact: Vec<ApiSummary>,
+    pub prefix: Vec<ApiSummary>,
+}
+
+/// api found by the request line
+#[derive(Debug, Clone)]
+pub struct ApiMatch {
+    pub api: Arc<ManagedApi>,
+    /// target path with the parameters and the rest of the path (prefix match)
+    pub target_path: String,
+}
+
+// api in the router for the rank of its hosts (0: any host)
+#[derive(Debug, Clone)]
+struct Route {
+    api: Arc<ManagedApi>,
+    host_rank: usize,
+    // key of the base path pattern
+    key: String,
+}
+
+/// immutable after published, a new map is built for each change
 #[derive(Debug, Clone)]
 pub struct Map {
-    exact_match: HashMap<String, ManagedApi>,
-    prefix_match: Vec<ManagedApi>,
+    // in the inserted order
+    apis: Vec<Arc<ManagedApi>>,
+    router: Router<Route>,
+    // number of the latest apis of each base path pattern
+    latest_keys: HashMap<String, usize>,
+    /// revision of admin (0: unknown)
+    revision: u64,
 }
 
 impl Map {
     // constructor
     pub fn new() -> Self {
-        let exact_match: HashMap<String, ManagedApi> = HashMap::new();
-        let prefix_match: Vec<ManagedApi> = Vec::new();
         Map {
-            exact_match,
-            prefix_match,
+            apis: Vec::new(),
+            router: Router::new(),
+            latest_keys: HashMap::new(),
+            revision: 0,
         }
     }
 
-    // find api
-    pub fn find(&self, method: &str, uri: &str) -> Option<ManagedApi> {
-        // 1. get from exact map
-        match self.exact_match.get(uri) {
-            Some(m_api) => {
-                // found
-                for api_method in m_api.de_api.methods.iter() {
-                    if method.eq(api_method) {
-                        // ToDo: need to deep copy?
-                        return Some(m_api.clone());
-                    }
-                }
-            }
-            None => {}
+    // find api: exact > {param} > longest prefix, for each segment
+    // (the apis of the same path: by the priority, the first one matching the request)
+    // "/v2/users" is version 2 of "/users" if it matches more of the path than "/v2/users"
+    // (a prefix api like "/*" doesn't hide the versions in the path)
+    pub fn find(&self, req: &RouteRequest) -> Option<ApiMatch> {
+        let found = self.find_path(req);
+        let versioned = matcher::split_version(req.path).and_then(|(version, path)| {
+            let (api_match, matched) = self.find_path(&RouteRequest {
+                path,
+                version: Some(version),
+                ..req.clone()
+            })?;
+            // the version segment is matched too
+            Some((api_match, matched + req.path.len() - path.len()))
+        });
+        match (found, versioned) {
+            (Some(found), Some(versioned)) if versioned.1 > found.1 => Some(versioned.0),
+            (Some(found), _) => Some(found.0),
+            (None, versioned) => versioned.map(|(api_match, _)| api_match),
+        }
+    }
+
+    // private: find api by the path of the request, with the length of the path matched
+    fn find_path(&self, req: &RouteRequest) -> Option<(ApiMatch, usize)> {
+        let found = self.router.find(req.path, |route| {
+            let path_has_latest = self.latest_keys.contains_key(&route.key);
+            route.api.matches(req, route.host_rank, path_has_latest)
+        })?;
+        let m_api = &found.value.api;
+        let api_match = ApiMatch {
+            api: m_api.clone(),
+            target_path: make_target_path(
+                &m_api.de_api.target_path,
+                &found.params,
+                &found.remaining,
+            ),
         };
+        Some((api_match, req.path.len() - found.remaining.len()))
+    }
 
-        // 2. get from prefix map ..
-        for m_api in &self.prefix_match {
-            let uri_len = uri.len();
-            let prefix_len = m_api.de_api.base_path.len();
-            let prefix_path = &m_api.de_api.base_path[..];
-
-            if prefix_len <= uri_len {
-                let sliced_uri = &uri[..prefix_len];
-                if sliced_uri.eq(prefix_path) {
-                    // found
-                    for api_method in m_api.de_api.methods.iter() {
-                        if method.eq(api_method) {
-                            // ToDo: need to deep copy?
-                            let mut found_api = m_api.clone();
-                            let remaining_uri = &uri[prefix_len - 1..];
-                            found_api.de_api.target_path.push_str(remaining_uri);
-
-                            // target_path has been changed! -> use target_path for proxy request
-                            return Some(found_api);
+    /// summary of the entries sorted by the base path
+    pub fn dump(&self) -> MapDump {
+        let mut exact = Vec::new();
+        let mut prefix = Vec::new();
+        for m_api in &self.apis {
+            let summary = ApiSummary::from(m_api.as_ref());
+            if m_api.match_prefix {
+                prefix.push(summary);
+            } else {
+                exact.push(summary);
+            }
+        }
+        exact.sort_by(|a, b| a.base_path.cmp(&b.base_path));
+        prefix.sort_by(|a, b| a.base_path.cmp(&b.base_path));
+        MapDump {
+            revision: self.revision,
+            exact,
+            prefix,
+        }
+    }
+
+    /// path and methods of the apis
+    pub fn route_keys(&self) -> Vec<RouteKey> {
+        self.apis
+            .iter()
+            .filter_map(|m_api| RouteKey::from_managed(m_api))
+            .collect()
+    }
+
+    // private: remove the api of the id and version
+    fn remove(&mut self, id: &str, version: usize) {
+        let same = |m_api: &ManagedApi| m_api.de_api._id == id && m_api.de_api.version == version;
+        for m_api in self.apis.iter().filter(|m_api| same(m_api)) {
+            if let Ok(pattern) = m_api.pattern() {
+                self.router.remove(&pattern, |route| same(&route.api));
+                if m_api.de_api.latest_version {
+                    let key = pattern.key();
+                    if let Some(count) = self.latest_keys.get_mut(&key) {
+                        *count -= 1;
+                        if *count == 0 {
+                            self.latest_keys.remove(&key);
                         }
                     }
                 }
             }
         }
-
-        // Not Found
-        None
+        self.apis.retain(|m_api| !same(m_api));
     }
 
-    // private: clear hashmap/vector
-    fn clear(&mut self) {
-        self.exact_match.clear();
-        self.prefix_match.clear();
+    // private: apply the changes
+    fn apply(&mut self, delta: &ApiDelta) {
+        for api_ref in &delta.delete {
+            self.remove(&api_ref.id, api_ref.version);
+        }
+        for de_api in &delta.upsert {
+            // base path may be changed
+            self.remove(&de_api._id, de_api.version);
+            self.insert(de_api.clone());
+        }
     }
 
     // private: insert new api: ToDo: protocol에 사용되는 구조체와 분리 방안
-    fn insert(&mut self, mut de_api: DeserializedApi) {
+    fn insert(&mut self, de_api: DeserializedApi) {
         let m_api = ManagedApi::new(de_api);
 
-        println!("\nAPI.MAP.Insert => {:?}", m_api);
-        if m_api.match_prefix {
-            self.prefix_match.push(m_api);
-        } else {
-            let key = m_api.get_key();
-            self.exact_match.insert(key, m_api);
+        debug!("API.MAP.Insert => {:?}", m_api);
+        // validated before, never fails
+        let pattern = match m_api.pattern() {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                warn!("api {} is not inserted: {}", m_api.de_api.name, e);
+                return;
+            }
+        };
+        let m_api = Arc::new(m_api);
+        let key = pattern.key();
+        if m_api.de_api.latest_version {
+            *self.latest_keys.entry(key.clone()).or_default() += 1;
         }
+        for host_rank in m_api.host_ranks() {
+            let route = Route {
+                api: m_api.clone(),
+                host_rank,
+                key: key.clone(),
+            };
+            self.router
+                .insert(&pattern, m_api.priority(host_rank), route);
+        }
+        self.apis.push(m_api);
     }
 }
 
-///! find_api_by_reqline
-pub fn find_api_by_reqline(method: &str, uri: &str) -> Option<ManagedApi> {
-    let view = get_gloval_view();
-    println!(
-        "find_api_by_uri: uri={}, view={} (0.left, 1.right)",
-        uri, view
-    );
-    if view == 0 {
-        // from LEFT map
-        GLOBAL_API_MAP_LEFT.read().unwrap().find(method, uri)
-    } else {
-        // from RIGHT map
-        GLOBAL_API_MAP_RIGHT.read().unwrap().find(method, uri)
-    }
+/// find_api_by_request (no lock, the map is not changed while it's used)
+pub fn find_api_by_request(req: &RouteRequest) -> Option<ApiMatch> {
+    trace!("find_api_by_request: host={:?}, uri={}", req.host, req.path);
+    GLOBAL_API_MAP.load().find(req)
 }
 
-fn clear_old_map() {
-    let view = get_gloval_view();
-    if view == 0 {
-        // left
-        GLOBAL_API_MAP_RIGHT.write().unwrap().clear();
-    } else {
-        // right
-        GLOBAL_API_MAP_LEFT.write().unwrap().clear();
-    }
+/// entries of the map used by the requests now
+pub fn dump_current_map() -> MapDump {
+    GLOBAL_API_MAP.load().dump()
 }
 
-fn insert_api_into_new_map(api: DeserializedApi) {
-    let view = get_gloval_view();
-    if view == 0 {
-        // left
-        GLOBAL_API_MAP_RIGHT.write().unwrap().insert(api);
-    } else {
-        // right
-        GLOBAL_API_MAP_LEFT.write().unwrap().insert(api);
-    }
+// store the new map for the requests (the writer lock is held)
+fn publish(map: Map) {
+    GLOBAL_API_MAP.store(Arc::new(map));
+    GLOBAL_API_MAP_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// changed when the map is replaced
+pub fn get_generation() -> u64 {
+    GLOBAL_API_MAP_GENERATION.load(Ordering::SeqCst)
 }
 
-pub fn insert_apis_into_new_map(apis: Vec<DeserializedApi>) {
-    // 1. clear
-    clear_old_map();
+/// (id, version) of the apis in the current map
+pub fn get_api_keys() -> HashSet<(String, usize)> {
+    GLOBAL_API_MAP
+        .load()
+        .apis
+        .iter()
+        .map(|m_api| (m_api.de_api._id.clone(), m_api.de_api.version))
+        .collect()
+}
+
+/// revision of admin applied to the map (0: unknown)
+pub fn get_revision() -> u64 {
+    GLOBAL_API_MAP.load().revision
+}
+
+/// apply the changes of admin to a copy of the map (the entries are shared)
+/// (Err: the base revision is not the current one, the map is not changed)
+pub fn apply_delta(delta: &ApiDelta) -> Result<(), String> {
+    let _writer = GLOBAL_API_MAP_WRITER.lock().unwrap();
+    let mut map = Map::clone(&GLOBAL_API_MAP.load());
+    if delta.base_revision != map.revision {
+        return Err(format!(
+            "base revision {} is not the current revision {}",
+            delta.base_revision, map.revision
+        ));
+    }
+
+    // 1. the invalid upserts are not applied (the old version stays)
+    let (valid_delta, rejects) = validate::validate_delta(map.route_keys(), delta);
+    validate::update_rejects(delta, rejects);
 
     // 2. update
+    map.apply(&valid_delta);
+    map.revision = delta.revision;
+
+    // 3. complete: publish
+    publish(map);
+
+    info!(
+        "--- global api map changed by delta --- revision: {}, upsert: {}, delete: {}",
+        delta.revision,
+        valid_delta.upsert.len(),
+        delta.delete.len()
+    );
+    Ok(())
+}
+
+/// replace the whole apis of the revision (0: not from admin)
+pub fn insert_apis_into_new_map(apis: Vec<DeserializedApi>, revision: u64) {
+    let _writer = GLOBAL_API_MAP_WRITER.lock().unwrap();
+
+    // 1. the invalid apis are not applied
+    let (apis, rejects) = validate::validate_apis(vec![], apis);
+    validate::set_rejects(rejects);
+
+    // 2. build
+    let mut map = Map::new();
+    map.revision = revision;
+    let count = apis.len();
     for de_api in apis {
-        insert_api_into_new_map(de_api);
+        map.insert(de_api);
     }
 
-    // 3. complete: change view
-    change_global_view();
+    // 3. complete: publish
+    publish(map);
 
-    println!("--- global api map chaned --- view: {}", get_gloval_view());
+    info!("--- global api map changed --- apis: {}", count);
 }
 
 /* -------------------------------[for test]--------------------------------- */
-///! Be careful: must be called orderly clear -> insert -> complete
+/// Be careful: must be called orderly clear -> insert -> complete
 ///  or use bulk insert function: buik_insert_into_new_map()
+#[allow(dead_code)]
 pub async fn test_update_apis() {
     let test_api1 = r#"
         {
@@ -266,35 +573,40 @@ pub async fn test_update_apis() {
     let api1 = serde_json::from_str(test_api1).unwrap();
     let api2 = serde_json::from_str(test_api2).unwrap();
     let apis = vec![api1, api2];
-    insert_apis_into_new_map(apis);
+    insert_apis_into_new_map(apis, 0);
 
     // sleep 3 seconds.
     std::thread::sleep(Duration::from_millis(3000));
 }
 
 // Todo: remove this after test
+#[allow(dead_code)]
 pub async fn test_find_apis() {
     // sleep 2 seconds.
     std::thread::sleep(Duration::from_millis(2000));
 
-    println!("===============test find api map ===============");
-    let found_api = find_api_by_reqline("GET", "/v1/test");
+    debug!("===============test find api map ===============");
+    let req = http::Request::get("/v1/test").body(()).unwrap();
+    let found_api = find_api_by_request(&RouteRequest::from_request(&req));
     match found_api {
         Some(api) => {
-            println!("Found! > {:?}", api);
+            debug!("Found! > {:?}", api);
         }
         None => {
-            println!("Not found!> /v1/test ");
+            debug!("Not found!> /v1/test ");
         }
     };
 
-    let found_api = find_api_by_reqline("POST", "/v2/naver/favicon.ico");
+    let req = http::Request::post("/v2/naver/favicon.ico")
+        .body(())
+        .unwrap();
+    let found_api = find_api_by_request(&RouteRequest::from_request(&req));
     match found_api {
         Some(api) => {
-            println!("Found! > {:?}", api);
+            debug!("Found! > {:?}", api);
         }
         None => {
-            println!("Not found!> /v2/naver/favicon.ico ");
+            debug!("Not found!> /v2/naver/favicon.ico ");
         }
     };
 }
@@ -1,3 +1,10 @@
+use super::ip_filter::{Cidr, ForwardedHeader, IpRules};
+use super::limit::{ConcurrencyLimit, SizeLimit};
+use crate::admin::address::{self, AdminAddress};
+use crate::logger::rotate::{RotateInterval, RotatePolicy};
+use crate::logger::ship::SyslogTarget;
+use crate::logger::system::{LogFilter, SystemLogFormat};
+use crate::trace::TraceConfig;
 use clap::Parser;
 use std::env;
 use std::net::SocketAddr;
@@ -8,8 +15,24 @@ use std::net::SocketAddr;
 #[clap(version = "1.0")]
 #[clap(about = "Osori is awesome API Gateway", long_about = None)]
 struct Args {
-    #[clap(short='a', long, name="address (ip:port)", help="set address to connect to admin (ENV: OSORI_ADMIN)", parse(try_from_str=validate_ip_address))]
-    admin_address: Option<String>,
+    #[clap(short='a', long, name="address (ip:port[/base path])", help="set addresses to connect to admin, comma separated for failover (ENV: OSORI_ADMIN)", use_value_delimiter=true, parse(try_from_str=AdminAddress::parse))]
+    admin_address: Option<Vec<AdminAddress>>,
+
+    #[clap(
+        short = 'f',
+        long,
+        name = "config file path",
+        conflicts_with = "address (ip:port[/base path])",
+        help = "run without admin by the config and apis in the file: yaml, json, toml (ENV: OSORI_CONFIG_FILE)"
+    )]
+    config_file: Option<String>,
+
+    #[clap(
+        long,
+        name = "snapshot file path",
+        help = "keep the last config of admin in the file and boot from it if admin is unreachable (ENV: OSORI_SNAPSHOT)"
+    )]
+    snapshot: Option<String>,
 
     #[clap(
         short = 'n',
@@ -35,6 +58,121 @@ struct Args {
     )]
     health_check_timeout: Option<usize>,
 
+    #[clap(long, name="redis address (ip:port)", help="set redis server to share rate limit counters in the group (ENV: OSORI_RATE_LIMIT_REDIS)", parse(try_from_str=validate_ip_address))]
+    rate_limit_redis: Option<String>,
+
+    #[clap(
+        long,
+        name = "count",
+        help = "set max number of requests proxied at the same time"
+    )]
+    max_concurrent: Option<usize>,
+
+    #[clap(
+        long,
+        name = "queue size",
+        help = "set max number of requests waiting for --max-concurrent",
+        requires = "count"
+    )]
+    max_queue: Option<usize>,
+
+    #[clap(
+        long,
+        name = "percent",
+        help = "shed requests while cpu usage is over the percent"
+    )]
+    shed_cpu_usage: Option<f32>,
+
+    #[clap(long, name="allowed cidr", multiple_occurrences=true, help="allow only the clients in the CIDR range (ex. 10.0.0.0/8)", parse(try_from_str=parse_cidr))]
+    allow_ip: Vec<Cidr>,
+
+    #[clap(long, name="denied cidr", multiple_occurrences=true, help="deny the clients in the CIDR range", parse(try_from_str=parse_cidr))]
+    deny_ip: Vec<Cidr>,
+
+    #[clap(long, name="proxy cidr", multiple_occurrences=true, help="trust the forwarded header set by the proxies in the CIDR range", parse(try_from_str=parse_cidr))]
+    trusted_proxy: Vec<Cidr>,
+
+    #[clap(long, name="header", help="set the header the trusted proxies append the client to: x-forwarded-for, forwarded (default: x-forwarded-for)", parse(try_from_str=ForwardedHeader::parse))]
+    forwarded_header: Option<ForwardedHeader>,
+
+    #[clap(
+        long,
+        name = "header count",
+        help = "set max number of request headers"
+    )]
+    max_header_count: Option<usize>,
+
+    #[clap(long, name = "header bytes", help = "set max bytes of request headers")]
+    max_header_size: Option<usize>,
+
+    #[clap(long, name = "uri length", help = "set max length of request uri")]
+    max_uri_length: Option<usize>,
+
+    #[clap(
+        long,
+        name = "file path",
+        help = "write access log to the file instead of stdout"
+    )]
+    access_log: Option<String>,
+
+    #[clap(
+        long,
+        name = "MB",
+        help = "rotate access log when it's larger than the size"
+    )]
+    access_log_max_size: Option<u64>,
+
+    #[clap(long, name="interval", help="rotate access log every hour or day: hourly, daily", parse(try_from_str=RotateInterval::parse))]
+    access_log_rotate: Option<RotateInterval>,
+
+    #[clap(long, help = "gzip the rotated access logs")]
+    access_log_compress: bool,
+
+    #[clap(
+        long,
+        name = "files",
+        help = "set number of the rotated access logs to keep"
+    )]
+    access_log_retention: Option<usize>,
+
+    #[clap(long, name="syslog (udp|tcp://host:port)", help="ship access log to syslog server", parse(try_from_str=SyslogTarget::parse))]
+    access_log_syslog: Option<SyslogTarget>,
+
+    #[clap(
+        long,
+        name = "url",
+        help = "ship access log to http collector as json lines",
+        parse(try_from_str=validate_url)
+    )]
+    access_log_http: Option<String>,
+
+    #[clap(
+        long,
+        name = "level",
+        help = "set system log level until admin sends it (ex. info, warn,service::proxy=debug)"
+    )]
+    log_level: Option<String>,
+
+    #[clap(long, name="format", help="set system log format: text, json", parse(try_from_str=SystemLogFormat::parse))]
+    log_format: Option<SystemLogFormat>,
+
+    #[clap(long, name="metrics address (ip:port)", help="serve prometheus metrics on the address (GET /metrics)", parse(try_from_str=parse_socket_address))]
+    metrics_address: Option<SocketAddr>,
+
+    #[clap(long, name="status address (loopback ip:port)", help="serve engine status as json on the address (GET /status), localhost only", parse(try_from_str=parse_loopback_address))]
+    status_address: Option<SocketAddr>,
+
+    #[clap(
+        long,
+        name = "collector url",
+        help = "export traces to OTLP/HTTP collector (ex. http://127.0.0.1:4318)",
+        parse(try_from_str=validate_url)
+    )]
+    otlp_endpoint: Option<String>,
+
+    #[clap(long, name="ratio", help="sample ratio of the new traces: 0.0 ~ 1.0 (default 1.0)", requires="collector url", parse(try_from_str=parse_ratio))]
+    trace_sample_ratio: Option<f64>,
+
     #[clap(short='s', long,name="signal", help="send signal to osori: stop", parse(try_from_str=signal_in_rage))]
     signal: Option<String>,
 
@@ -53,77 +191,196 @@ fn validate_ip_address(s: &str) -> Result<String, String> {
     match s.parse::<SocketAddr>() {
         Ok(_socket) => {
             // can check ip address format and port number range
-            return Ok(s.to_string());
+            Ok(s.to_string())
         }
-        Err(e) => return Err(e.to_string()),
-    };
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_socket_address(s: &str) -> Result<SocketAddr, String> {
+    s.parse::<SocketAddr>().map_err(|e| e.to_string())
+}
+
+// status shows the internals of the engine, never listen on the network
+fn parse_loopback_address(s: &str) -> Result<SocketAddr, String> {
+    let address = parse_socket_address(s)?;
+    if !address.ip().is_loopback() {
+        return Err(String::from("Not a loopback address (ex. 127.0.0.1:5590)"));
+    }
+    Ok(address)
+}
+
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        _ => Err(String::from("Invalid ratio (0.0 ~ 1.0)")),
+    }
+}
+
+fn validate_url(s: &str) -> Result<String, String> {
+    match s.parse::<hyper::Uri>() {
+        Ok(uri) if uri.scheme().is_some() && uri.host().is_some() => Ok(s.to_string()),
+        Ok(_) => Err(String::from("Invalid url (ex. http://127.0.0.1:8080/logs)")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_cidr(s: &str) -> Result<Cidr, String> {
+    s.parse::<Cidr>()
 }
 
 fn signal_in_rage(s: &str) -> Result<String, String> {
     let signal_list = ["stop", "reload"];
-    if signal_list.iter().any(|&v| v == s) {
+    if signal_list.contains(&s) {
         return Ok(s.to_string());
     }
 
-    Err(format!("Invalid signal option"))
+    Err(String::from("Invalid signal option"))
 }
 
 pub struct SystemConfig {
-    pub admin_address: String,
+    /// empty in standalone mode
+    pub admin_address: Vec<AdminAddress>,
+    pub config_file: Option<String>,
+    pub snapshot: Option<String>,
     pub engine_name: Option<String>,
     pub group_name: Option<String>,
+    pub rate_limit_redis: Option<String>,
+    pub concurrency_limit: Option<ConcurrencyLimit>,
+    pub shed_cpu_usage: Option<f32>,
+    pub ip_filter: Option<IpRules>,
+    pub trusted_proxies: Vec<Cidr>,
+    pub forwarded_header: ForwardedHeader,
+    pub size_limit: SizeLimit,
+    pub access_log: Option<String>,
+    pub access_log_rotate: RotatePolicy,
+    pub access_log_syslog: Option<SyslogTarget>,
+    pub access_log_http: Option<String>,
+    pub log_level: String,
+    pub log_format: SystemLogFormat,
+    pub verbose: bool,
+    pub metrics_address: Option<SocketAddr>,
+    pub status_address: Option<SocketAddr>,
+    pub trace: Option<TraceConfig>,
 }
 
 pub fn parse() -> Result<SystemConfig, String> {
     let args = Args::parse();
 
-    if args.verbose_mode {
-        // TODO: verbose mode
-        println!("Verbose mode is not yet implemented.");
-    }
+    // check the level before the logger starts
+    let log_level = args.log_level.unwrap_or_else(|| String::from("info"));
+    LogFilter::parse(&log_level)?;
+
+    // get local config file (standalone mode)
+    let config_file = match args.config_file {
+        Some(path) => Some(path),
+        None if args.admin_address.is_none() => env::var("OSORI_CONFIG_FILE").ok(),
+        None => None,
+    };
 
-    // get admin server address
+    // get admin server address (not used in standalone mode)
     let admin_address = match args.admin_address {
         Some(address) => address,
+        None if config_file.is_some() => vec![],
         None => {
             if let Ok(address) = env::var("OSORI_ADMIN") {
-                if let Err(_e) = validate_ip_address(address.as_str()) {
-                    return Err(String::from("Address of admin server is not valid (ex. 127.0.0.1:5581). check ENV OSORI_ADMIN"));
+                match address::parse_list(&address) {
+                    Ok(addresses) => addresses,
+                    Err(e) => return Err(format!("Address of admin server is not valid (ex. 127.0.0.1:5581): {}. check ENV OSORI_ADMIN", e)),
                 }
-                address
             } else {
-                return Err(String::from("Address of admin server is required (ex. 127.0.0.1:5581). use ENV OSORI_ADMIN or -a option"));
+                return Err(String::from("Address of admin server is required (ex. 127.0.0.1:5581). use ENV OSORI_ADMIN or -a option, or -f option to run without admin"));
             }
         }
     };
 
+    // get snapshot file
+    let snapshot = match args.snapshot {
+        Some(path) => Some(path),
+        None => env::var("OSORI_SNAPSHOT").ok(),
+    };
+
     // get engine name
     let engine_name = match args.engine_name {
         Some(name) => Some(name),
-        None => {
-            if let Ok(name) = env::var("OSORI_ENGINE") {
-                Some(name)
-            } else {
-                None
-            }
-        }
+        None => env::var("OSORI_ENGINE").ok(),
     };
 
     // get group name
     let group_name = match args.group_name {
         Some(name) => Some(name),
-        None => {
-            if let Ok(name) = env::var("OSORI_GROUP") {
-                Some(name)
-            } else {
-                None
+        None => env::var("OSORI_GROUP").ok(),
+    };
+
+    // get redis address for cluster-wide rate limit
+    let rate_limit_redis = match args.rate_limit_redis {
+        Some(address) => Some(address),
+        None => match env::var("OSORI_RATE_LIMIT_REDIS") {
+            Ok(address) => {
+                if let Err(_e) = validate_ip_address(address.as_str()) {
+                    return Err(String::from("Address of redis server is not valid (ex. 127.0.0.1:6379). check ENV OSORI_RATE_LIMIT_REDIS"));
+                }
+                Some(address)
             }
-        }
+            Err(_e) => None,
+        },
+    };
+
+    // get global concurrency limit
+    let concurrency_limit = args.max_concurrent.map(|max_concurrent| ConcurrencyLimit {
+        max_concurrent,
+        max_queue: args.max_queue.unwrap_or_default(),
+        queue_timeout: 0,
+    });
+
+    // get global ip filter
+    let ip_filter = if args.allow_ip.is_empty() && args.deny_ip.is_empty() {
+        None
+    } else {
+        Some(IpRules {
+            allow: args.allow_ip,
+            deny: args.deny_ip,
+        })
     };
 
+    // get otlp collector for tracing
+    let trace = args.otlp_endpoint.map(|endpoint| TraceConfig {
+        endpoint,
+        sample_ratio: args.trace_sample_ratio.unwrap_or(1.0),
+        service_name: String::from("osori"),
+    });
+
     Ok(SystemConfig {
         admin_address,
+        config_file,
+        snapshot,
         engine_name,
         group_name,
+        rate_limit_redis,
+        concurrency_limit,
+        shed_cpu_usage: args.shed_cpu_usage,
+        ip_filter,
+        trusted_proxies: args.trusted_proxy,
+        forwarded_header: args.forwarded_header.unwrap_or_default(),
+        size_limit: SizeLimit {
+            max_header_count: args.max_header_count,
+            max_header_size: args.max_header_size,
+            max_uri_length: args.max_uri_length,
+        },
+        access_log: args.access_log,
+        access_log_rotate: RotatePolicy {
+            max_size: args.access_log_max_size.map(|mb| mb * 1024 * 1024),
+            interval: args.access_log_rotate,
+            compress: args.access_log_compress,
+            retention: args.access_log_retention,
+        },
+        access_log_syslog: args.access_log_syslog,
+        access_log_http: args.access_log_http,
+        log_level,
+        log_format: args.log_format.unwrap_or(SystemLogFormat::Text),
+        verbose: args.verbose_mode,
+        metrics_address: args.metrics_address,
+        status_address: args.status_address,
+        trace,
     })
 }
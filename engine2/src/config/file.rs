@@ -0,0 +1,95 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_file.rs"]
+mod test_file;
+
+use super::{api, system};
+use log::{info, warn};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tokio::{task, time};
+
+// how often the file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// what admin sends on register, from a local file (standalone mode)
+#[derive(Deserialize)]
+pub struct LocalConfig {
+    /// None: keep the config from the command line
+    #[serde(default)]
+    pub config: Option<system::SystemConfig>,
+    #[serde(default)]
+    pub api: Vec<api::DeserializedApi>,
+}
+
+/// parse by the extension of the file: yaml, yml, json, toml
+pub fn parse(path: &Path, data: &str) -> Result<LocalConfig, String> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "yaml" | "yml" => serde_yaml::from_str(data).map_err(|e| e.to_string()),
+        "json" => serde_json::from_str(data).map_err(|e| e.to_string()),
+        "toml" => toml::from_str(data).map_err(|e| e.to_string()),
+        _ => Err(String::from(
+            "Unknown config file type (use .yaml, .yml, .json or .toml)",
+        )),
+    }
+}
+
+pub fn load(path: &Path) -> Result<LocalConfig, String> {
+    let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse(path, &data)
+}
+
+pub fn apply(local: LocalConfig) {
+    if let Some(config) = local.config {
+        system::apply(&config);
+    }
+    api::insert_apis_into_new_map(local.api, 0);
+}
+
+// changed if the modified time or the size is different
+fn get_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// load and apply the file, then reload it when it's changed
+pub fn handle(path: String) -> Result<(), String> {
+    let path = Path::new(&path).to_path_buf();
+    let mut version = get_version(&path);
+    apply(load(&path)?);
+    info!("standalone: config is loaded from {}", path.display());
+
+    task::spawn(async move {
+        let mut interval = time::interval(WATCH_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let current = get_version(&path);
+            if current.is_none() || current == version {
+                continue;
+            }
+            version = current;
+
+            // the current config stays if the file is broken
+            match load(&path) {
+                Ok(local) => {
+                    apply(local);
+                    info!("standalone: config is reloaded from {}", path.display());
+                }
+                Err(e) => warn!(
+                    "standalone: failed to reload {}, keep the current config: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    });
+    Ok(())
+}
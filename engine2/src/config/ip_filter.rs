@@ -0,0 +1,131 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_ip_filter.rs"]
+mod test_ip_filter;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// ip access control of an api (pushed by admin in `DeserializedApi::ip_filter`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct IpFilter {
+    /// CIDR ranges allowed to call (empty: everyone)
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// CIDR ranges denied to call (checked before allow)
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+/// ip address range (ex. 10.0.0.0/8, 2001:db8::/32, 127.0.0.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        // ::ffff:a.b.c.d is compared as ipv4
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*ip),
+            IpAddr::V4(_) => *ip,
+        };
+
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|e| format!("invalid CIDR '{}': {}", s, e))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => match prefix.parse::<u8>() {
+                Ok(prefix) if prefix <= max => prefix,
+                _ => return Err(format!("invalid CIDR '{}': bad prefix length", s)),
+            },
+            None => max,
+        };
+
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// parsed allow/deny list
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IpRules {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>,
+}
+
+impl IpRules {
+    pub fn parse(filter: &IpFilter) -> Result<Self, String> {
+        Ok(IpRules {
+            allow: parse_cidrs(&filter.allow)?,
+            deny: parse_cidrs(&filter.deny)?,
+        })
+    }
+
+    /// the rule which denies the ip, or None if allowed
+    pub fn find_denial(&self, ip: &IpAddr) -> Option<String> {
+        if let Some(cidr) = self.deny.iter().find(|cidr| cidr.contains(ip)) {
+            return Some(format!("deny {}", cidr));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|cidr| cidr.contains(ip)) {
+            return Some(String::from("not in allow list"));
+        }
+        None
+    }
+}
+
+pub fn parse_cidrs(list: &[String]) -> Result<Vec<Cidr>, String> {
+    list.iter().map(|cidr| cidr.parse::<Cidr>()).collect()
+}
+
+/// header the trusted proxies append the client address to (the other one is ignored)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ForwardedHeader {
+    #[default]
+    XForwardedFor,
+    Forwarded,
+}
+
+impl ForwardedHeader {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "x-forwarded-for" => Ok(ForwardedHeader::XForwardedFor),
+            "forwarded" => Ok(ForwardedHeader::Forwarded),
+            _ => Err(String::from(
+                "Invalid forwarded header (x-forwarded-for, forwarded)",
+            )),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+
+/// rate limit rule of an api (pushed by admin in `DeserializedApi::rate_limits`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    /// what the counter is keyed by
+    pub limit_by: RateLimitKey,
+    /// claim name in the jwt payload (only for `jwtClaim`)
+    #[serde(default)]
+    pub claim: String,
+    #[serde(default)]
+    pub algorithm: RateLimitAlgorithm,
+    /// allowed requests per window
+    pub limit: u64,
+    /// window size in seconds
+    pub window: u64,
+    /// bucket size of token bucket (default: limit)
+    #[serde(default)]
+    pub burst: u64,
+    /// share the counter with the engines in the same group
+    #[serde(default)]
+    pub cluster: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum RateLimitKey {
+    Api,
+    ApiKey,
+    ClientIp,
+    JwtClaim,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum RateLimitAlgorithm {
+    #[default]
+    TokenBucket,
+    SlidingWindow,
+}
+
+impl RateLimit {
+    pub fn window_secs(&self) -> u64 {
+        self.window.max(1)
+    }
+
+    pub fn capacity(&self) -> u64 {
+        if self.burst > 0 {
+            self.burst
+        } else {
+            self.limit
+        }
+    }
+}
+
+/// limit of in-flight requests (per api or global)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConcurrencyLimit {
+    /// requests proxied at the same time
+    pub max_concurrent: usize,
+    /// requests waiting for a slot, the others are shed immediately
+    #[serde(default)]
+    pub max_queue: usize,
+    /// seconds to wait in the queue (0: no timeout)
+    #[serde(default)]
+    pub queue_timeout: u64,
+}
+
+/// global limits of the request line and headers
+#[derive(Debug, Clone, Default)]
+pub struct SizeLimit {
+    pub max_header_count: Option<usize>,
+    /// bytes of all header names and values
+    pub max_header_size: Option<usize>,
+    pub max_uri_length: Option<usize>,
+}
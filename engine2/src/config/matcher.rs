@@ -0,0 +1,165 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_matcher.rs"]
+mod test_matcher;
+
+use http::header::{HeaderName, HeaderValue, HOST};
+use http::{HeaderMap, Request};
+use serde::{Deserialize, Serialize};
+
+/// header of the version of the api requested
+pub const ACCEPT_VERSION: &str = "accept-version";
+
+/// header or query parameter of the request to match (`DeserializedApi::headers`, `query`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub struct ValueMatch {
+    pub name: String,
+    /// None: any value, the name must be in the request
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+/// parts of the request used to find the api
+#[derive(Debug, Clone)]
+pub struct RouteRequest<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    /// without the port, lowercase
+    pub host: Option<String>,
+    pub headers: &'a HeaderMap,
+    pub query: Option<&'a str>,
+    /// version of the api requested (None: the latest version)
+    pub version: Option<usize>,
+}
+
+impl<'a> RouteRequest<'a> {
+    pub fn from_request<B>(req: &'a Request<B>) -> Self {
+        // Host header (http/1.1) or the authority (http/2)
+        let host = req
+            .headers()
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| req.uri().host())
+            .map(strip_port)
+            .map(|host| host.to_ascii_lowercase());
+        RouteRequest {
+            method: req.method().as_str(),
+            path: req.uri().path(),
+            host,
+            headers: req.headers(),
+            query: req.uri().query(),
+            version: requested_version(req.headers(), req.uri().query()),
+        }
+    }
+}
+
+// "2" or "v2"
+fn parse_version(version: &str) -> Option<usize> {
+    let version = version.trim();
+    version
+        .strip_prefix(['v', 'V'])
+        .unwrap_or(version)
+        .parse()
+        .ok()
+}
+
+/// version in Accept-Version header, or version query parameter
+pub fn requested_version(headers: &HeaderMap, query: Option<&str>) -> Option<usize> {
+    if let Some(version) = headers.get(ACCEPT_VERSION) {
+        return version.to_str().ok().and_then(parse_version);
+    }
+    query
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("version="))
+        .and_then(parse_version)
+}
+
+/// "/v2/users/1" -> (2, "/users/1")
+pub fn split_version(path: &str) -> Option<(usize, &str)> {
+    let rest = path.strip_prefix("/v")?;
+    let (version, rest) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    if version.is_empty() || !version.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((version.parse().ok()?, rest))
+}
+
+// "example.com:8080" -> "example.com", "[::1]:8080" -> "[::1]"
+fn strip_port(host: &str) -> &str {
+    match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    }
+}
+
+/// host of the request matches the pattern ("*.example.com": any subdomain)
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix('*') {
+        Some(suffix) => {
+            host.len() > suffix.len()
+                && host[host.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+        }
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
+
+/// one of the values of the header matches
+pub fn header_matches(rule: &ValueMatch, headers: &HeaderMap) -> bool {
+    let mut values = headers.get_all(rule.name.as_str()).iter();
+    match &rule.value {
+        Some(expected) => values.any(|value| value.as_bytes() == expected.as_bytes()),
+        None => values.next().is_some(),
+    }
+}
+
+/// one of the parameters matches (compared without decoding)
+pub fn query_matches(rule: &ValueMatch, query: Option<&str>) -> bool {
+    query
+        .unwrap_or_default()
+        .split('&')
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .any(|(name, value)| {
+            name == rule.name
+                && match &rule.value {
+                    Some(expected) => value == expected,
+                    None => true,
+                }
+        })
+}
+
+/// errors of the host pattern
+pub fn validate_host(host: &str) -> Result<(), String> {
+    let name = host.strip_prefix("*.").unwrap_or(host);
+    let valid = !name.is_empty()
+        && name.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if !valid {
+        return Err(format!("host {} is invalid", host));
+    }
+    Ok(())
+}
+
+/// errors of the header to match
+pub fn validate_header(rule: &ValueMatch) -> Result<(), String> {
+    HeaderName::from_bytes(rule.name.as_bytes())
+        .map_err(|_| format!("header name {} is invalid", rule.name))?;
+    if let Some(value) = &rule.value {
+        HeaderValue::from_str(value)
+            .map_err(|_| format!("value of header {} is invalid", rule.name))?;
+    }
+    Ok(())
+}
+
+/// errors of the query parameter to match
+pub fn validate_query(rule: &ValueMatch) -> Result<(), String> {
+    if rule.name.is_empty() || rule.name.contains(['&', '=', '#']) {
+        return Err(format!("query parameter name {} is invalid", rule.name));
+    }
+    Ok(())
+}
@@ -1,3 +1,11 @@
 pub mod api;
 pub mod args;
+pub mod file;
+pub mod ip_filter;
+pub mod limit;
+pub mod matcher;
+pub mod router;
 pub mod system;
+#[cfg(test)]
+pub mod test_fixture;
+pub mod validate;
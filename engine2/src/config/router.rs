@@ -0,0 +1,342 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_router.rs"]
+mod test_router;
+
+use std::collections::HashMap;
+
+/// path pattern of a route
+/// "/users/{id}" : exact, "{id}" matches a segment
+/// "/users/*"    : prefix, "*" matches the rest of the path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    /// segments before the wildcard, None: "{param}"
+    segments: Vec<Option<String>>,
+    params: Vec<String>,
+    /// Some: prefix match, the rest of the path starts with it
+    /// ("/v1/ec*" matches "/v1/echo", rest prefix is "ec")
+    wildcard: Option<String>,
+}
+
+fn parse_param(segment: &str) -> Result<Option<String>, String> {
+    let name = match segment
+        .strip_prefix('{')
+        .and_then(|segment| segment.strip_suffix('}'))
+    {
+        Some(name) => name,
+        None if segment.contains(['{', '}']) => {
+            return Err(format!(
+                "segment {} has '{{' or '}}' not as a parameter",
+                segment
+            ))
+        }
+        None => return Ok(None),
+    };
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(format!("parameter name {{{}}} is invalid", name));
+    }
+    Ok(Some(name.to_string()))
+}
+
+impl Pattern {
+    /// path of the pattern starts with '/' (prefix: without '*')
+    pub fn parse(path: &str, prefix: bool) -> Result<Self, String> {
+        if !path.starts_with('/') {
+            return Err(format!("path {} doesn't start with '/'", path));
+        }
+        let (head, wildcard) = if prefix {
+            // the wildcard starts after the last '/'
+            let slash = path.rfind('/').unwrap_or_default();
+            let rest = &path[slash + 1..];
+            if rest.contains(['{', '}']) {
+                return Err(format!("parameter {} is not allowed before '*'", rest));
+            }
+            (&path[..slash], Some(rest.to_string()))
+        } else {
+            (path, None)
+        };
+
+        let mut segments = Vec::new();
+        let mut params = Vec::new();
+        // "/" for exact is one empty segment, same as the request path
+        if !head.is_empty() || !prefix {
+            for segment in head[1..].split('/') {
+                match parse_param(segment)? {
+                    Some(name) => {
+                        if params.contains(&name) {
+                            return Err(format!("parameter {{{}}} is duplicated", name));
+                        }
+                        params.push(name);
+                        segments.push(None);
+                    }
+                    None => segments.push(Some(segment.to_string())),
+                }
+            }
+        }
+        Ok(Pattern {
+            segments,
+            params,
+            wildcard,
+        })
+    }
+
+    /// names of the parameters in the order
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    /// same paths are matched by both patterns (parameter names are ignored)
+    pub fn key(&self) -> String {
+        let mut key = String::new();
+        for segment in &self.segments {
+            key.push('/');
+            key.push_str(segment.as_deref().unwrap_or("{}"));
+        }
+        if let Some(wildcard) = &self.wildcard {
+            key.push('/');
+            key.push_str(wildcard);
+            key.push('*');
+        }
+        key
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    value: T,
+    params: Vec<String>,
+    priority: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Wildcard<T> {
+    prefix: String,
+    entry: Entry<T>,
+}
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    statics: HashMap<String, Node<T>>,
+    param: Option<Box<Node<T>>>,
+    // routes ending at this node
+    exact: Vec<Entry<T>>,
+    // routes matching the rest of the path from this node, longer prefix first
+    wildcards: Vec<Wildcard<T>>,
+}
+
+impl<T> Node<T> {
+    fn new() -> Self {
+        Node {
+            statics: HashMap::new(),
+            param: None,
+            exact: Vec::new(),
+            wildcards: Vec::new(),
+        }
+    }
+}
+
+/// route found by the path
+#[derive(Debug, PartialEq, Eq)]
+pub struct RouteMatch<'a, T> {
+    pub value: &'a T,
+    /// (name, value) of the parameters
+    pub params: Vec<(String, String)>,
+    /// the rest of the path matched by the wildcard, starts with '/' for "/*"
+    pub remaining: String,
+}
+
+/// segment trie of the path patterns: lookup is O(path length), not the number of routes
+/// precedence in each segment: static > {param} > wildcard (longer prefix first),
+/// the routes of the same pattern are in the priority order (higher first, then inserted order)
+#[derive(Debug, Clone)]
+pub struct Router<T> {
+    root: Node<T>,
+}
+
+impl<T> Default for Router<T> {
+    fn default() -> Self {
+        Router::new()
+    }
+}
+
+impl<T> Router<T> {
+    pub fn new() -> Self {
+        Router { root: Node::new() }
+    }
+
+    fn node_mut(&mut self, pattern: &Pattern) -> &mut Node<T> {
+        let mut node = &mut self.root;
+        for segment in &pattern.segments {
+            node = match segment {
+                Some(segment) => node
+                    .statics
+                    .entry(segment.clone())
+                    .or_insert_with(Node::new),
+                None => node.param.get_or_insert_with(|| Box::new(Node::new())),
+            };
+        }
+        node
+    }
+
+    pub fn insert(&mut self, pattern: &Pattern, priority: usize, value: T) {
+        let entry = Entry {
+            value,
+            params: pattern.params.clone(),
+            priority,
+        };
+        let node = self.node_mut(pattern);
+        match &pattern.wildcard {
+            Some(prefix) => {
+                // after the routes of the longer prefix, or the same prefix and not lower priority
+                let index = node
+                    .wildcards
+                    .iter()
+                    .position(|wildcard| {
+                        wildcard.prefix.len() < prefix.len()
+                            || (wildcard.prefix.len() == prefix.len()
+                                && wildcard.entry.priority < priority)
+                    })
+                    .unwrap_or(node.wildcards.len());
+                node.wildcards.insert(
+                    index,
+                    Wildcard {
+                        prefix: prefix.clone(),
+                        entry,
+                    },
+                );
+            }
+            None => {
+                let index = node
+                    .exact
+                    .iter()
+                    .position(|exact| exact.priority < priority)
+                    .unwrap_or(node.exact.len());
+                node.exact.insert(index, entry);
+            }
+        }
+    }
+
+    /// remove the routes of the pattern which the predicate is true (empty nodes stay)
+    pub fn remove<F>(&mut self, pattern: &Pattern, predicate: F)
+    where
+        F: Fn(&T) -> bool,
+    {
+        let node = self.node_mut(pattern);
+        match &pattern.wildcard {
+            Some(prefix) => node
+                .wildcards
+                .retain(|wildcard| &wildcard.prefix != prefix || !predicate(&wildcard.entry.value)),
+            None => node.exact.retain(|entry| !predicate(&entry.value)),
+        }
+    }
+
+    /// the most specific route of the path which the value is accepted (ex. method)
+    pub fn find<F>(&self, path: &str, accept: F) -> Option<RouteMatch<'_, T>>
+    where
+        F: Fn(&T) -> bool,
+    {
+        if !path.starts_with('/') {
+            return None;
+        }
+        // (start, end) of the segments in the path
+        let mut segments = Vec::new();
+        let mut start = 1;
+        for (i, c) in path.char_indices().skip(1) {
+            if c == '/' {
+                segments.push((start, i));
+                start = i + 1;
+            }
+        }
+        segments.push((start, path.len()));
+
+        let mut values = Vec::new();
+        let (entry, remaining) = find_in(&self.root, path, &segments, 0, &mut values, &accept)?;
+        Some(RouteMatch {
+            value: &entry.value,
+            params: entry.params.iter().cloned().zip(values).collect(),
+            remaining,
+        })
+    }
+}
+
+fn find_in<'a, T, F>(
+    node: &'a Node<T>,
+    path: &str,
+    segments: &[(usize, usize)],
+    depth: usize,
+    values: &mut Vec<String>,
+    accept: &F,
+) -> Option<(&'a Entry<T>, String)>
+where
+    F: Fn(&T) -> bool,
+{
+    let (start, end) = match segments.get(depth) {
+        Some(segment) => *segment,
+        None => {
+            let entry = node.exact.iter().find(|entry| accept(&entry.value))?;
+            return Some((entry, String::new()));
+        }
+    };
+    let segment = &path[start..end];
+
+    // 1. static
+    if let Some(child) = node.statics.get(segment) {
+        if let Some(found) = find_in(child, path, segments, depth + 1, values, accept) {
+            return Some(found);
+        }
+    }
+
+    // 2. {param} (not empty, "." and ".." would change the target path)
+    if let Some(child) = &node.param {
+        if !matches!(segment, "" | "." | "..") {
+            values.push(segment.to_string());
+            if let Some(found) = find_in(child, path, segments, depth + 1, values, accept) {
+                return Some(found);
+            }
+            values.pop();
+        }
+    }
+
+    // 3. wildcard
+    let rest = &path[start..];
+    for wildcard in &node.wildcards {
+        if rest.starts_with(&wildcard.prefix) && accept(&wildcard.entry.value) {
+            let remaining = if wildcard.prefix.is_empty() {
+                // "/*": keep the '/' before the rest
+                path[start - 1..].to_string()
+            } else {
+                rest[wildcard.prefix.len()..].to_string()
+            };
+            return Some((&wildcard.entry, remaining));
+        }
+    }
+    None
+}
+
+/// target path of the request: {param} of the target is replaced, and the remaining is appended
+pub fn make_target_path(target: &str, params: &[(String, String)], remaining: &str) -> String {
+    // one pass: "{name}" in the values is not substituted again
+    let mut path = target
+        .split('/')
+        .map(|segment| {
+            let value = segment
+                .strip_prefix('{')
+                .and_then(|segment| segment.strip_suffix('}'))
+                .and_then(|name| params.iter().find(|(param, _)| param == name));
+            match value {
+                Some((_, value)) => value.as_str(),
+                None => segment,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    if path.ends_with('/') && remaining.starts_with('/') {
+        path.pop();
+    }
+    path.push_str(remaining);
+    path
+}
@@ -1,6 +1,23 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_system.rs"]
+mod test_system;
+
+use crate::logger::{access, system};
+use lazy_static::lazy_static;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::RwLock;
+
+const REDACTED: &str = "***";
+
+lazy_static! {
+    // the last config pushed by admin
+    static ref CURRENT: RwLock<Option<SystemConfig>> = RwLock::new(None);
+}
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SystemConfig {
     pub _id: String,
     #[serde(rename = "accessLogFormat")]
@@ -14,7 +31,7 @@ pub struct SystemConfig {
     pub threads: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct HttpsConfig {
     pub _id: String,
     #[serde(rename = "certificateFileData")]
@@ -28,3 +45,41 @@ pub struct HttpsConfig {
     #[serde(rename = "privateKeyFileName")]
     pub private_key_file_name: String,
 }
+
+/// apply the config pushed by admin (on register and "config" action of poll)
+pub fn apply(config: &SystemConfig) {
+    access::set_format(&config.access_log_format);
+
+    // empty level keeps the current one
+    if !config.system_log_level.is_empty() {
+        match system::set_level(&config.system_log_level) {
+            Ok(()) => info!("system log level: {}", config.system_log_level),
+            Err(e) => warn!("system log level is not changed: {}", e),
+        }
+    }
+
+    *CURRENT.write().unwrap() = Some(config.clone());
+}
+
+impl SystemConfig {
+    /// json of the config without the secrets (key, certificate, password)
+    pub fn to_redacted_json(&self) -> Value {
+        let mut config = self.clone();
+        let https = &mut config.listen_https;
+        for secret in [
+            &mut https.certificate_file_data,
+            &mut https.private_key_file_data,
+            &mut https.password,
+        ] {
+            if !secret.is_empty() {
+                *secret = String::from(REDACTED);
+            }
+        }
+        serde_json::to_value(config).unwrap_or_default()
+    }
+}
+
+/// the current config (None before register)
+pub fn get_current() -> Option<SystemConfig> {
+    CURRENT.read().unwrap().clone()
+}
@@ -0,0 +1,207 @@
+#[cfg(test)]
+mod test_api {
+    use super::super::*;
+    use crate::config::test_fixture::make_api;
+
+    fn find(map: &Map, method: &str, uri: &str) -> Option<ApiMatch> {
+        let req = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .unwrap();
+        map.find(&RouteRequest::from_request(&req))
+    }
+
+    fn api_ref(id: &str, version: usize) -> ApiRef {
+        ApiRef {
+            id: id.to_string(),
+            version,
+        }
+    }
+
+    #[test]
+    fn test_map_apply_delta() {
+        let mut map = Map::new();
+        map.insert(make_api("a", 1, "/a", &["GET"]));
+        map.insert(make_api("b", 1, "/b/*", &["GET"]));
+        map.insert(make_api("c", 1, "/c", &["GET"]));
+
+        let delta = ApiDelta {
+            base_revision: 1,
+            revision: 2,
+            // base path of "a" is changed
+            upsert: vec![
+                make_api("a", 1, "/a2", &["GET"]),
+                make_api("d", 1, "/d/*", &["GET"]),
+            ],
+            delete: vec![api_ref("b", 1), api_ref("c", 2)],
+        };
+        map.apply(&delta);
+
+        assert!(find(&map, "GET", "/a").is_none());
+        assert_eq!(find(&map, "GET", "/a2").unwrap().api.de_api._id, "a");
+        assert!(find(&map, "GET", "/b/x").is_none());
+        // version 2 of "c" doesn't exist
+        assert!(find(&map, "GET", "/c").is_some());
+        assert_eq!(find(&map, "GET", "/d/x").unwrap().api.de_api._id, "d");
+    }
+
+    #[test]
+    fn test_delta_apply_to_list() {
+        let mut apis = vec![
+            make_api("a", 1, "/a", &["GET"]),
+            make_api("a", 2, "/a/v2", &["GET"]),
+        ];
+        let delta = ApiDelta {
+            base_revision: 0,
+            revision: 1,
+            upsert: vec![make_api("a", 2, "/a/v2/new", &["GET"])],
+            delete: vec![api_ref("a", 1)],
+        };
+        delta.apply_to(&mut apis);
+
+        assert_eq!(apis.len(), 1);
+        assert_eq!(apis[0].base_path, "/a/v2/new");
+    }
+
+    #[test]
+    fn test_map_find_shares_api() {
+        let mut map = Map::new();
+        let mut api = make_api("a", 1, "/a/*", &["GET"]);
+        api.target_path = String::from("/x*");
+        map.insert(api);
+
+        let found = find(&map, "GET", "/a/b/c").unwrap();
+        assert_eq!(found.target_path, "/x/b/c");
+        // not cloned for the request
+        assert!(Arc::ptr_eq(&found.api, &map.apis[0]));
+        assert_eq!(map.apis[0].de_api.target_path, "/x");
+
+        assert!(find(&map, "POST", "/a/b").is_none());
+        assert!(find(&map, "GET", "/b").is_none());
+    }
+
+    #[test]
+    fn test_map_find_by_host_and_header() {
+        let mut map = Map::new();
+        map.insert(make_api("any", 1, "/a", &["GET"]));
+        let mut api = make_api("wildcard", 1, "/a", &["GET"]);
+        api.hosts = vec![String::from("*.example.com")];
+        map.insert(api);
+        let mut api = make_api("canary", 1, "/a", &["GET"]);
+        api.hosts = vec![String::from("*.example.com")];
+        api.headers = vec![ValueMatch {
+            name: String::from("x-canary"),
+            value: Some(String::from("on")),
+        }];
+        map.insert(api);
+        let mut api = make_api("exact", 1, "/a", &["GET"]);
+        api.hosts = vec![String::from("api.example.com")];
+        map.insert(api);
+        let mut api = make_api("mixed", 1, "/a", &["GET"]);
+        api.hosts = vec![
+            String::from("*.example.com"),
+            String::from("www.example.com"),
+        ];
+        map.insert(api);
+
+        let find = |host: &str, canary: Option<&str>| {
+            let mut req = http::Request::builder().uri("/a").header("host", host);
+            if let Some(canary) = canary {
+                req = req.header("X-Canary", canary);
+            }
+            let req = req.body(()).unwrap();
+            let found = map.find(&RouteRequest::from_request(&req)).unwrap();
+            found.api.de_api._id.clone()
+        };
+        assert_eq!(find("API.example.com:3000", None), "exact");
+        assert_eq!(find("a.example.com", Some("on")), "canary");
+        assert_eq!(find("a.example.com", Some("off")), "wildcard");
+        // ranked by the host matched
+        assert_eq!(find("www.example.com", Some("on")), "mixed");
+        assert_eq!(find("example.com", Some("on")), "any");
+    }
+
+    #[test]
+    fn test_map_find_version() {
+        let mut map = Map::new();
+        let mut api = make_api("a", 1, "/users/*", &["GET", "POST"]);
+        api.latest_version = false;
+        map.insert(api);
+        map.insert(make_api("a", 2, "/users/*", &["GET"]));
+        map.insert(make_api("b", 1, "/v1/echo", &["GET"]));
+
+        let find_method = |method: &str, uri: &str, version: Option<&str>| {
+            let mut req = http::Request::builder().method(method).uri(uri);
+            if let Some(version) = version {
+                req = req.header("Accept-Version", version);
+            }
+            let req = req.body(()).unwrap();
+            let found = map.find(&RouteRequest::from_request(&req))?;
+            Some((found.api.de_api.version, found.target_path))
+        };
+        let find = |uri: &str, version: Option<&str>| find_method("GET", uri, version);
+        assert_eq!(find("/users/7", None), Some((2, String::from("/7"))));
+        assert_eq!(find("/users/7", Some("v1")), Some((1, String::from("/7"))));
+        assert_eq!(
+            find("/users/7?version=1", None),
+            Some((1, String::from("/7")))
+        );
+        assert_eq!(find("/v1/users/7", None), Some((1, String::from("/7"))));
+        // no version 3: not another version of the api
+        assert_eq!(find("/users/7", Some("3")), None);
+        assert_eq!(find("/v3/users/7", None), None);
+        assert_eq!(find("/v1/echo", Some("2")), None);
+        // the latest version doesn't allow the method
+        assert_eq!(find_method("POST", "/users/7", None), None);
+        assert_eq!(
+            find_method("POST", "/users/7", Some("1")),
+            Some((1, String::from("/7")))
+        );
+        // the base path is matched before the version in the path
+        assert_eq!(find("/v1/echo", None), Some((1, String::from("/"))));
+    }
+
+    #[test]
+    fn test_map_find_version_with_prefix() {
+        let mut map = Map::new();
+        map.insert(make_api("root", 1, "/*", &["GET"]));
+        let mut api = make_api("a", 1, "/users/*", &["GET"]);
+        api.latest_version = false;
+        map.insert(api);
+        map.insert(make_api("a", 2, "/users/*", &["GET"]));
+        map.insert(make_api("b", 1, "/v1/echo", &["GET"]));
+
+        let find = |uri: &str| {
+            let req = http::Request::builder().uri(uri).body(()).unwrap();
+            let found = map.find(&RouteRequest::from_request(&req))?;
+            Some((found.api.de_api._id.clone(), found.api.de_api.version))
+        };
+        assert_eq!(find("/v1/users/7"), Some((String::from("a"), 1)));
+        assert_eq!(find("/users/7"), Some((String::from("a"), 2)));
+        assert_eq!(find("/v1/echo"), Some((String::from("b"), 1)));
+        assert_eq!(find("/v3/users/7"), Some((String::from("root"), 1)));
+        assert_eq!(find("/other"), Some((String::from("root"), 1)));
+    }
+
+    #[test]
+    fn test_map_find_without_latest() {
+        let mut map = Map::new();
+        map.insert(make_api("root", 1, "/*", &["GET"]));
+        for version in [1, 3, 2] {
+            let mut api = make_api("a", version, "/legacy", &["GET"]);
+            api.latest_version = false;
+            map.insert(api);
+        }
+
+        // no api of the path is the latest: the highest version
+        let req = http::Request::builder().uri("/legacy").body(()).unwrap();
+        let found = map.find(&RouteRequest::from_request(&req)).unwrap();
+        assert_eq!(found.api.de_api._id, "a");
+        assert_eq!(found.api.de_api.version, 3);
+
+        map.remove("a", 3);
+        let found = map.find(&RouteRequest::from_request(&req)).unwrap();
+        assert_eq!(found.api.de_api.version, 2);
+    }
+}
@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod test_file {
+    use super::super::*;
+
+    const YAML: &str = r#"
+api:
+  - id: "api1"
+    name: "echo"
+    version: 1
+    latestVersion: true
+    methods: ["GET", "POST"]
+    basePath: "/v1/echo/*"
+    targetPath: "/*"
+    targetServers: ["http://127.0.0.1:8081"]
+    authType: "none"
+    apiKeys: []
+    cors: false
+    author: "local"
+    createTime: ""
+    description: ""
+    engineGroups: []
+"#;
+
+    const TOML: &str = r#"
+[[api]]
+id = "api1"
+name = "echo"
+version = 1
+latestVersion = true
+methods = ["GET", "POST"]
+basePath = "/v1/echo/*"
+targetPath = "/*"
+targetServers = ["http://127.0.0.1:8081"]
+authType = "none"
+apiKeys = []
+cors = false
+author = "local"
+createTime = ""
+description = ""
+engineGroups = []
+"#;
+
+    #[test]
+    fn test_parse() {
+        for (path, data) in [("osori.yaml", YAML), ("osori.toml", TOML)] {
+            let local = parse(Path::new(path), data).unwrap();
+            assert!(local.config.is_none());
+            assert_eq!(local.api.len(), 1);
+            assert_eq!(local.api[0].base_path, "/v1/echo/*");
+            assert_eq!(local.api[0].methods, vec!["GET", "POST"]);
+        }
+
+        let local = parse(Path::new("osori.json"), r#"{"api": []}"#).unwrap();
+        assert!(local.api.is_empty());
+
+        assert!(parse(Path::new("osori.yaml"), "api: 1").is_err());
+        assert!(parse(Path::new("osori.ini"), YAML).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod test_ip_filter {
+    use super::super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let cidr: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(cidr.contains(&ip("10.1.2.3")));
+        assert!(cidr.contains(&ip("::ffff:10.1.2.3")));
+        assert!(!cidr.contains(&ip("10.2.0.1")));
+        assert!(!cidr.contains(&ip("2001:db8::1")));
+
+        let cidr: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(cidr.contains(&ip("2001:db8:cafe::17")));
+        assert!(!cidr.contains(&ip("2001:db9::1")));
+
+        let cidr: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(cidr.contains(&ip("192.168.0.1")));
+
+        let cidr: Cidr = "127.0.0.1".parse().unwrap();
+        assert_eq!(cidr.to_string(), "127.0.0.1/32");
+        assert!(!cidr.contains(&ip("127.0.0.2")));
+    }
+
+    #[test]
+    fn test_cidr_parse_error() {
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("::/129".parse::<Cidr>().is_err());
+        assert!("localhost".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_find_denial() {
+        let rules = IpRules::parse(&IpFilter {
+            allow: vec!["10.0.0.0/8".into()],
+            deny: vec!["10.0.0.1".into()],
+        })
+        .unwrap();
+
+        assert_eq!(rules.find_denial(&ip("10.0.0.2")), None);
+        assert_eq!(
+            rules.find_denial(&ip("10.0.0.1")),
+            Some("deny 10.0.0.1/32".to_string())
+        );
+        assert_eq!(
+            rules.find_denial(&ip("192.168.0.1")),
+            Some("not in allow list".to_string())
+        );
+    }
+}
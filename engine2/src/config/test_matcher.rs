@@ -0,0 +1,70 @@
+#[cfg(test)]
+mod test_matcher {
+    use super::super::*;
+
+    fn rule(name: &str, value: Option<&str>) -> ValueMatch {
+        ValueMatch {
+            name: name.to_string(),
+            value: value.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_host_matches() {
+        assert!(host_matches("example.com", "EXAMPLE.com"));
+        assert!(host_matches("*.example.com", "a.example.com"));
+        assert!(host_matches("*.example.com", "a.b.example.com"));
+        assert!(!host_matches("*.example.com", "example.com"));
+        assert!(!host_matches("*.example.com", "aexample.com"));
+        assert_eq!(strip_port("example.com:8080"), "example.com");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+        assert_eq!(strip_port("[::1]"), "[::1]");
+    }
+
+    #[test]
+    fn test_value_matches() {
+        let mut headers = HeaderMap::new();
+        headers.append("x-tenant", "a".parse().unwrap());
+        headers.append("x-tenant", "b".parse().unwrap());
+        assert!(header_matches(&rule("X-Tenant", Some("b")), &headers));
+        assert!(header_matches(&rule("x-tenant", None), &headers));
+        assert!(!header_matches(&rule("x-tenant", Some("c")), &headers));
+        assert!(!header_matches(&rule("x-other", None), &headers));
+
+        assert!(query_matches(
+            &rule("canary", Some("1")),
+            Some("a=2&canary=1")
+        ));
+        assert!(query_matches(&rule("debug", None), Some("debug&a=2")));
+        assert!(!query_matches(
+            &rule("canary", Some("1")),
+            Some("canary=10")
+        ));
+        assert!(!query_matches(&rule("canary", None), None));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate_host("*.example.com").is_ok());
+        assert!(validate_host("127.0.0.1").is_ok());
+        assert!(validate_host("*").is_err());
+        assert!(validate_host("a..com").is_err());
+        assert!(validate_host("example.com:80").is_err());
+        assert!(validate_header(&rule("bad header", None)).is_err());
+        assert!(validate_query(&rule("a=b", None)).is_err());
+    }
+
+    #[test]
+    fn test_version() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(requested_version(&headers, Some("a=1&version=v3")), Some(3));
+        assert_eq!(requested_version(&headers, Some("a=1")), None);
+        headers.insert(ACCEPT_VERSION, "2".parse().unwrap());
+        assert_eq!(requested_version(&headers, Some("version=3")), Some(2));
+
+        assert_eq!(split_version("/v2/users/1"), Some((2, "/users/1")));
+        assert_eq!(split_version("/v10"), Some((10, "/")));
+        assert_eq!(split_version("/video/1"), None);
+        assert_eq!(split_version("/v/1"), None);
+    }
+}
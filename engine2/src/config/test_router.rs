@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod test_router {
+    use super::super::*;
+
+    fn make_router(routes: &[(&str, &'static str)]) -> Router<&'static str> {
+        let mut router = Router::new();
+        for (path, value) in routes {
+            let pattern = match path.strip_suffix('*') {
+                Some(path) => Pattern::parse(path, true),
+                None => Pattern::parse(path, false),
+            };
+            router.insert(&pattern.unwrap(), 0, *value);
+        }
+        router
+    }
+
+    fn find(router: &Router<&'static str>, path: &str) -> Option<&'static str> {
+        router.find(path, |_| true).map(|found| *found.value)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Pattern::parse("/users/{id}", false).unwrap().key(),
+            "/users/{}"
+        );
+        assert_eq!(Pattern::parse("/v1/ec", true).unwrap().key(), "/v1/ec*");
+        assert_eq!(Pattern::parse("/", true).unwrap().key(), "/*");
+        assert!(Pattern::parse("users", false).is_err());
+        assert!(Pattern::parse("/users/{}", false).is_err());
+        assert!(Pattern::parse("/users/a{id}", false).is_err());
+        assert!(Pattern::parse("/{id}/{id}", false).is_err());
+        assert!(Pattern::parse("/users/{id", true).is_err());
+    }
+
+    #[test]
+    fn test_precedence() {
+        let router = make_router(&[
+            ("/*", "root"),
+            ("/v1/*", "v1"),
+            ("/v1/users/*", "users"),
+            ("/v1/users/{id}", "user"),
+            ("/v1/users/me", "me"),
+            ("/v1/ec*", "ec"),
+        ]);
+        assert_eq!(find(&router, "/v1/users/me"), Some("me"));
+        assert_eq!(find(&router, "/v1/users/7"), Some("user"));
+        assert_eq!(find(&router, "/v1/users/7/posts"), Some("users"));
+        assert_eq!(find(&router, "/v1/users/"), Some("users"));
+        assert_eq!(find(&router, "/v1/users"), Some("v1"));
+        assert_eq!(find(&router, "/v1/echo"), Some("ec"));
+        assert_eq!(find(&router, "/v2"), Some("root"));
+    }
+
+    #[test]
+    fn test_backtracking() {
+        let router = make_router(&[("/a/{x}/c", "param"), ("/a/b/d", "static")]);
+        // the static segment "b" doesn't lead to "c"
+        let found = router.find("/a/b/c", |_| true).unwrap();
+        assert_eq!(*found.value, "param");
+        assert_eq!(found.params, vec![(String::from("x"), String::from("b"))]);
+
+        // the value isn't accepted (ex. method)
+        let router = make_router(&[("/a", "get"), ("/*", "post")]);
+        assert_eq!(*router.find("/a", |v| *v == "post").unwrap().value, "post");
+        assert!(router.find("/a", |v| *v == "put").is_none());
+    }
+
+    #[test]
+    fn test_target_path() {
+        let router = make_router(&[("/users/{id}/*", "a"), ("/files/*", "b")]);
+
+        let found = router.find("/users/7/posts/1", |_| true).unwrap();
+        assert_eq!(found.remaining, "/posts/1");
+        assert_eq!(
+            make_target_path("/members/{id}/", &found.params, &found.remaining),
+            "/members/7/posts/1"
+        );
+
+        let found = router.find("/files/a.txt", |_| true).unwrap();
+        assert_eq!(
+            make_target_path("/", &found.params, &found.remaining),
+            "/a.txt"
+        );
+
+        // the values are not substituted again
+        let params = vec![
+            (String::from("a"), String::from("{b}")),
+            (String::from("b"), String::from("x")),
+        ];
+        assert_eq!(make_target_path("/{a}/{b}", &params, ""), "/{b}/x");
+    }
+
+    #[test]
+    fn test_dot_segment() {
+        let router = make_router(&[("/users/{id}", "user"), ("/*", "root")]);
+        assert_eq!(find(&router, "/users/7"), Some("user"));
+        assert_eq!(find(&router, "/users/.."), Some("root"));
+        assert_eq!(find(&router, "/users/."), Some("root"));
+        assert_eq!(find(&router, "/users/..."), Some("user"));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut router = make_router(&[("/a/{id}", "a1"), ("/a/{name}", "a2"), ("/a/*", "b")]);
+        router.remove(&Pattern::parse("/a/{x}", false).unwrap(), |v| *v == "a1");
+        assert_eq!(find(&router, "/a/1"), Some("a2"));
+        router.remove(&Pattern::parse("/a/{x}", false).unwrap(), |_| true);
+        assert_eq!(find(&router, "/a/1"), Some("b"));
+    }
+}
@@ -0,0 +1,23 @@
+#[cfg(test)]
+mod test_system {
+    use super::super::*;
+    use crate::config::test_fixture::make_config;
+
+    #[test]
+    fn test_redacted_json() {
+        let json = make_config("secret").to_redacted_json();
+        let https = &json["listenHttps"];
+        assert_eq!(https["certificateFileData"], REDACTED);
+        assert_eq!(https["privateKeyFileData"], REDACTED);
+        assert_eq!(https["password"], REDACTED);
+        assert_eq!(https["certificateFileName"], "cert.pem");
+        assert_eq!(json["accessLogFormat"], "combined");
+        assert!(!json.to_string().contains("BEGIN"));
+    }
+
+    #[test]
+    fn test_redacted_json_empty_secret() {
+        let json = make_config("").to_redacted_json();
+        assert_eq!(json["listenHttps"]["password"], "");
+    }
+}
@@ -0,0 +1,137 @@
+#[cfg(test)]
+mod test_validate {
+    use super::super::*;
+    use crate::config::api::ApiRef;
+    use crate::config::test_fixture::make_api;
+
+    #[test]
+    fn test_validate_api() {
+        assert!(validate_api(&make_api("a", 1, "/a/*", &["GET"])).is_empty());
+
+        let mut api = make_api("a", 1, "", &["GET", "FETCH"]);
+        api.target_path = String::from("/x*");
+        api.target_servers = vec![String::from("ftp://host"), String::from("http://")];
+        let errors = validate_api(&api);
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+
+        assert_eq!(validate_api(&make_api("a", 1, "a/*/b", &["GET"])).len(), 2);
+        assert_eq!(validate_api(&make_api("a", 1, "/a", &[])).len(), 1);
+
+        let mut api = make_api("a", 1, "/users/{id}", &["GET"]);
+        api.target_path = String::from("/members/{id}/{name}");
+        assert_eq!(validate_api(&api).len(), 1);
+    }
+
+    fn with_hosts(mut api: DeserializedApi, hosts: &[&str]) -> DeserializedApi {
+        api.hosts = hosts.iter().map(|host| host.to_string()).collect();
+        api
+    }
+
+    fn with_version(mut api: DeserializedApi, version: usize, latest: bool) -> DeserializedApi {
+        api.version = version;
+        api.latest_version = latest;
+        api
+    }
+
+    #[test]
+    fn test_validate_apis() {
+        let apis = vec![
+            make_api("a", 1, "/a", &["GET"]),
+            make_api("b", 1, "/a", &["POST"]),
+            make_api("c", 1, "/a", &["GET", "PUT"]),
+            make_api("d", 1, "/v1/*", &["GET"]),
+            make_api("e", 1, "/v1/users/*", &["GET"]),
+            make_api("f", 1, "/v1/users/*", &["POST"]),
+            make_api("g", 1, "", &["GET"]),
+            make_api("h", 1, "/v1/users/{id}", &["GET"]),
+            make_api("i", 1, "/v1/users/{name}", &["GET"]),
+            make_api("j", 1, "/v1/{id}/{id}", &["GET"]),
+            with_hosts(make_api("k", 1, "/a", &["GET"]), &["a.example.com"]),
+            with_hosts(
+                make_api("l", 1, "/a", &["GET"]),
+                &["b.example.com", "A.example.com"],
+            ),
+            with_hosts(
+                make_api("m", 1, "/a", &["GET"]),
+                &["*.example.com", "bad host"],
+            ),
+            with_version(make_api("d", 1, "/v1/*", &["GET"]), 2, false),
+            with_version(make_api("d", 1, "/v1/*", &["GET"]), 3, true),
+        ];
+        let (accepted, rejects) = validate_apis(vec![], apis);
+
+        let ids: Vec<&str> = accepted.iter().map(|api| api._id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b", "d", "e", "f", "h", "k", "d"]);
+        let rejected: Vec<&str> = rejects.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(rejected, vec!["c", "g", "i", "j", "l", "m", "d"]);
+    }
+
+    #[test]
+    fn test_validate_delta() {
+        let (accepted, _) = validate_apis(
+            vec![],
+            vec![
+                make_api("a", 1, "/a", &["GET"]),
+                make_api("b", 1, "/b", &["GET"]),
+            ],
+        );
+        let map: Vec<RouteKey> = accepted.iter().filter_map(RouteKey::from_api).collect();
+
+        let delta = ApiDelta {
+            base_revision: 1,
+            revision: 2,
+            // "a" moves to "/b" where "b" was deleted, "c" conflicts with the new "a"
+            upsert: vec![
+                make_api("a", 1, "/b", &["GET"]),
+                make_api("c", 1, "/b", &["GET"]),
+            ],
+            delete: vec![ApiRef {
+                id: String::from("b"),
+                version: 1,
+            }],
+        };
+        let (valid, rejects) = validate_delta(map, &delta);
+        assert_eq!(valid.upsert.len(), 1);
+        assert_eq!(valid.upsert[0]._id, "a");
+        assert_eq!(valid.delete, delta.delete);
+        assert_eq!(rejects.len(), 1);
+        assert_eq!(rejects[0].id, "c");
+    }
+
+    #[test]
+    fn test_validate_delta_rejected_update() {
+        let (accepted, _) = validate_apis(vec![], vec![make_api("a", 1, "/a", &["GET"])]);
+        let map: Vec<RouteKey> = accepted.iter().filter_map(RouteKey::from_api).collect();
+
+        // the invalid update of "a" is rejected, "/a" is still used by the old "a"
+        let mut invalid = make_api("a", 1, "/b", &["GET"]);
+        invalid.target_servers.clear();
+        let delta = ApiDelta {
+            base_revision: 1,
+            revision: 2,
+            upsert: vec![invalid, make_api("c", 1, "/a", &["GET"])],
+            delete: vec![],
+        };
+        let (valid, rejects) = validate_delta(map.clone(), &delta);
+        assert!(valid.upsert.is_empty());
+        let rejected: Vec<&str> = rejects.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(rejected, vec!["a", "c"]);
+
+        // the update of "a" conflicts with "c", so "/a" is not freed for "d"
+        let delta = ApiDelta {
+            base_revision: 1,
+            revision: 2,
+            upsert: vec![
+                make_api("c", 1, "/c", &["GET"]),
+                make_api("a", 1, "/c", &["GET"]),
+                make_api("d", 1, "/a", &["GET"]),
+            ],
+            delete: vec![],
+        };
+        let (valid, rejects) = validate_delta(map, &delta);
+        let ids: Vec<&str> = valid.upsert.iter().map(|api| api._id.as_str()).collect();
+        assert_eq!(ids, vec!["c"]);
+        let rejected: Vec<&str> = rejects.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(rejected, vec!["a", "d"]);
+    }
+}
@@ -0,0 +1,360 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_validate.rs"]
+mod test_validate;
+
+use super::api::{ApiDelta, DeserializedApi, ManagedApi};
+use super::ip_filter::IpRules;
+use super::matcher::{self, ValueMatch};
+use super::router::Pattern;
+use lazy_static::lazy_static;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+const METHODS: [&str; 9] = [
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+lazy_static! {
+    // apis not in the map because of the errors
+    static ref REJECTS: Mutex<Vec<ApiReject>> = Mutex::new(Vec::new());
+}
+
+/// api which is not applied, reported to admin
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiReject {
+    pub id: String,
+    pub name: String,
+    pub version: usize,
+    pub errors: Vec<String>,
+}
+
+impl ApiReject {
+    fn new(api: &DeserializedApi, errors: Vec<String>) -> Self {
+        ApiReject {
+            id: api._id.clone(),
+            name: api.name.clone(),
+            version: api.version,
+            errors,
+        }
+    }
+}
+
+/// path, methods and conditions of an api in the map, to find the conflicts
+#[derive(Debug, Clone)]
+pub struct RouteKey {
+    id: String,
+    version: usize,
+    name: String,
+    /// same for the patterns matching the same paths
+    path: String,
+    methods: Vec<String>,
+    /// lowercase
+    hosts: Vec<String>,
+    latest_version: bool,
+    /// sorted (the order doesn't matter)
+    headers: Vec<ValueMatch>,
+    query: Vec<ValueMatch>,
+}
+
+impl RouteKey {
+    fn new(api: &DeserializedApi, pattern: &Pattern) -> Self {
+        RouteKey {
+            id: api._id.clone(),
+            version: api.version,
+            name: api.name.clone(),
+            path: pattern.key(),
+            methods: api.methods.clone(),
+            latest_version: api.latest_version,
+            hosts: api
+                .hosts
+                .iter()
+                .map(|host| host.to_ascii_lowercase())
+                .collect(),
+            headers: sorted(&api.headers, |rule| ValueMatch {
+                name: rule.name.to_ascii_lowercase(),
+                value: rule.value.clone(),
+            }),
+            query: sorted(&api.query, ValueMatch::clone),
+        }
+    }
+
+    fn from_api(api: &DeserializedApi) -> Option<Self> {
+        let (path, prefix) = match api.base_path.strip_suffix('*') {
+            Some(path) => (path, true),
+            None => (&api.base_path[..], false),
+        };
+        let pattern = Pattern::parse(path, prefix).ok()?;
+        Some(RouteKey::new(api, &pattern))
+    }
+
+    pub fn from_managed(m_api: &ManagedApi) -> Option<Self> {
+        let pattern = m_api.pattern().ok()?;
+        Some(RouteKey::new(&m_api.de_api, &pattern))
+    }
+
+    fn is_same_api(&self, api: &DeserializedApi) -> bool {
+        self.id == api._id && self.version == api.version
+    }
+
+    // the request of the path, method and host never reaches the new one
+    // (different prefixes are not conflicts, the longest one is matched,
+    //  different conditions are not conflicts, the more specific one is matched)
+    fn conflict(&self, new: &RouteKey) -> Option<String> {
+        if !self
+            .methods
+            .iter()
+            .any(|method| new.methods.contains(method))
+        {
+            return None;
+        }
+        if self.path != new.path || self.headers != new.headers || self.query != new.query {
+            return None;
+        }
+        let same_hosts = (self.hosts.is_empty() && new.hosts.is_empty())
+            || self.hosts.iter().any(|host| new.hosts.contains(host));
+        if !same_hosts {
+            return None;
+        }
+        // the versions of the same api (_id) share the path, the request selects one
+        // by /v{version}/ or gets the latest one: only two latest versions conflict.
+        // different apis on the same path are conflicts even with different versions
+        if self.id == new.id {
+            if self.latest_version && new.latest_version {
+                return Some(format!(
+                    "v{} is the latest version of the same base path",
+                    self.version
+                ));
+            }
+            return None;
+        }
+        Some(format!(
+            "base path {} is used by {} (v{})",
+            self.path, self.name, self.version
+        ))
+    }
+}
+
+fn sorted<F>(rules: &[ValueMatch], f: F) -> Vec<ValueMatch>
+where
+    F: Fn(&ValueMatch) -> ValueMatch,
+{
+    let mut rules: Vec<ValueMatch> = rules.iter().map(f).collect();
+    rules.sort();
+    rules
+}
+
+fn validate_path(name: &str, path: &str, errors: &mut Vec<String>) {
+    if path.is_empty() {
+        errors.push(format!("{} is empty", name));
+        return;
+    }
+    if !path.starts_with('/') {
+        errors.push(format!("{} {} doesn't start with '/'", name, path));
+    }
+    if path.strip_suffix('*').unwrap_or(path).contains('*') {
+        errors.push(format!("{} {} has '*' not at the end", name, path));
+    }
+    if path.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        errors.push(format!(
+            "{} {} has a space or a control character",
+            name, path
+        ));
+    }
+}
+
+// {param} of the base path, and the ones used by the target path
+fn validate_params(api: &DeserializedApi, errors: &mut Vec<String>) {
+    let (path, prefix) = match api.base_path.strip_suffix('*') {
+        Some(path) => (path, true),
+        None => (&api.base_path[..], false),
+    };
+    let pattern = match Pattern::parse(path, prefix) {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            errors.push(format!("base path {} is invalid: {}", api.base_path, e));
+            return;
+        }
+    };
+    for segment in api.target_path.split('/') {
+        if let Some(name) = segment
+            .strip_prefix('{')
+            .and_then(|segment| segment.strip_suffix('}'))
+        {
+            if !pattern.params().iter().any(|param| param == name) {
+                errors.push(format!(
+                    "target path uses {{{}}} which is not in base path",
+                    name
+                ));
+            }
+        }
+    }
+}
+
+fn validate_target_server(server: &str) -> Result<(), String> {
+    let uri = server
+        .parse::<hyper::Uri>()
+        .map_err(|e| format!("target server {} is invalid: {}", server, e))?;
+    match uri.scheme_str() {
+        Some("http") | Some("https") => {}
+        _ => return Err(format!("target server {} is not http(s)", server)),
+    }
+    if uri.host().is_none() {
+        return Err(format!("target server {} has no host", server));
+    }
+    Ok(())
+}
+
+/// errors of the api itself (empty: valid)
+pub fn validate_api(api: &DeserializedApi) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    validate_path("base path", &api.base_path, &mut errors);
+    validate_path("target path", &api.target_path, &mut errors);
+    if errors.is_empty() {
+        validate_params(api, &mut errors);
+    }
+    if api.target_path.ends_with('*') && !api.base_path.ends_with('*') {
+        errors.push(String::from(
+            "target path ends with '*' but base path doesn't",
+        ));
+    }
+
+    if api.target_servers.is_empty() {
+        errors.push(String::from("target servers are empty"));
+    }
+    for server in &api.target_servers {
+        if let Err(e) = validate_target_server(server) {
+            errors.push(e);
+        }
+    }
+
+    if api.methods.is_empty() {
+        errors.push(String::from("methods are empty"));
+    }
+    for method in &api.methods {
+        if !METHODS.contains(&method.as_str()) {
+            errors.push(format!("method {} is unknown", method));
+        }
+    }
+
+    let results = api
+        .hosts
+        .iter()
+        .map(|host| matcher::validate_host(host))
+        .chain(api.headers.iter().map(matcher::validate_header))
+        .chain(api.query.iter().map(matcher::validate_query));
+    errors.extend(results.filter_map(Result::err));
+
+    if let Some(filter) = &api.ip_filter {
+        if let Err(e) = IpRules::parse(filter) {
+            errors.push(format!("ip filter is invalid: {}", e));
+        }
+    }
+    errors
+}
+
+/// the apis without errors or conflicts with the existing ones and the earlier ones in the list
+pub fn validate_apis(
+    existing: Vec<RouteKey>,
+    apis: Vec<DeserializedApi>,
+) -> (Vec<DeserializedApi>, Vec<ApiReject>) {
+    let mut accepted_keys = existing;
+    let mut accepted = Vec::new();
+    let mut rejects = Vec::new();
+
+    for api in apis {
+        let mut errors = validate_api(&api);
+        if errors.is_empty() {
+            // valid base path has the key
+            if let Some(key) = RouteKey::from_api(&api) {
+                errors.extend(
+                    accepted_keys
+                        .iter()
+                        .filter_map(|accepted| accepted.conflict(&key)),
+                );
+                if errors.is_empty() {
+                    accepted_keys.push(key);
+                    accepted.push(api);
+                    continue;
+                }
+            }
+        }
+        warn!(
+            "api {} (v{}) is rejected: {}",
+            api.name,
+            api.version,
+            errors.join(", ")
+        );
+        rejects.push(ApiReject::new(&api, errors));
+    }
+    (accepted, rejects)
+}
+
+/// the upserts of the delta without errors or conflicts with the map
+pub fn validate_delta(map: Vec<RouteKey>, delta: &ApiDelta) -> (ApiDelta, Vec<ApiReject>) {
+    let deleted = |key: &RouteKey| {
+        delta
+            .delete
+            .iter()
+            .any(|r| r.id == key.id && r.version == key.version)
+    };
+    // updated apis are replaced only if the upserts are accepted,
+    // the old keys of the rejected ones stay in the map
+    let mut replaced: Vec<&DeserializedApi> = delta
+        .upsert
+        .iter()
+        .filter(|api| validate_api(api).is_empty())
+        .collect();
+    loop {
+        let existing = map
+            .iter()
+            .filter(|key| !deleted(key) && !replaced.iter().any(|api| key.is_same_api(api)))
+            .cloned()
+            .collect();
+        let (upsert, rejects) = validate_apis(existing, delta.upsert.clone());
+        // an old key back in the map can reject more upserts
+        let count = replaced.len();
+        replaced.retain(|api| {
+            upsert
+                .iter()
+                .any(|accepted| accepted._id == api._id && accepted.version == api.version)
+        });
+        if replaced.len() == count {
+            let delta = ApiDelta {
+                base_revision: delta.base_revision,
+                revision: delta.revision,
+                upsert,
+                delete: delta.delete.clone(),
+            };
+            return (delta, rejects);
+        }
+    }
+}
+
+/// rejects of the whole apis
+pub fn set_rejects(rejects: Vec<ApiReject>) {
+    *REJECTS.lock().unwrap() = rejects;
+}
+
+/// rejects after the delta: the apis in the delta are rejected again or not
+pub fn update_rejects(delta: &ApiDelta, rejects: Vec<ApiReject>) {
+    let mut current = REJECTS.lock().unwrap();
+    current.retain(|reject| {
+        !delta
+            .delete
+            .iter()
+            .any(|r| r.id == reject.id && r.version == reject.version)
+            && !delta
+                .upsert
+                .iter()
+                .any(|api| api._id == reject.id && api.version == reject.version)
+    });
+    current.extend(rejects);
+}
+
+pub fn get_rejects() -> Vec<ApiReject> {
+    REJECTS.lock().unwrap().clone()
+}
@@ -0,0 +1,333 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_access.rs"]
+mod test_access;
+
+use super::rotate::{RotatePolicy, RotatingFile};
+use super::ship;
+use super::time::DateTime;
+use lazy_static::lazy_static;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// records waiting for the writer, the others are dropped
+const QUEUE_SIZE: usize = 8192;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+const COMBINED_FORMAT: &str = r#"{client_ip} - - [{time}] "{method} {uri} {protocol}" {status} {response_bytes} "{referer}" "{user_agent}""#;
+const COMBINED_ID_FORMAT: &str = r#"{client_ip} - - [{time}] "{method} {uri} {protocol}" {status} {response_bytes} "{referer}" "{user_agent}" "{request_id}""#;
+
+lazy_static! {
+    static ref ACCESS_LOG_FORMAT: RwLock<Arc<AccessLogFormat>> =
+        RwLock::new(Arc::new(AccessLogFormat::default()));
+    static ref ACCESS_LOG_SENDER: RwLock<Option<SyncSender<AccessLogRecord>>> = RwLock::new(None);
+}
+
+// records dropped because the writer was too slow
+static DROPPED_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// information of a finished request
+#[derive(Debug, Clone)]
+pub struct AccessLogRecord {
+    pub request_id: String,
+    pub client_ip: String,
+    pub time: SystemTime,
+    pub method: String,
+    pub uri: String,
+    pub protocol: String,
+    pub status: u16,
+    pub upstream: String,
+    pub latency: Duration,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+    pub api_name: String,
+    pub api_version: usize,
+    pub user_agent: String,
+    pub referer: String,
+}
+
+impl AccessLogRecord {
+    fn path(&self) -> &str {
+        self.uri.split('?').next().unwrap_or("")
+    }
+
+    fn query(&self) -> &str {
+        self.uri
+            .split_once('?')
+            .map(|(_, query)| query)
+            .unwrap_or("")
+    }
+
+    // value of the template variable, or None if it's unknown
+    fn variable(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "request_id" => dash_if_empty(&self.request_id),
+            "client_ip" => self.client_ip.clone(),
+            "time" => DateTime::from(self.time).to_clf(),
+            "time_iso8601" => DateTime::from(self.time).to_iso8601(),
+            "method" => self.method.clone(),
+            "uri" => self.uri.clone(),
+            "path" => self.path().to_string(),
+            "query" => self.query().to_string(),
+            "protocol" => self.protocol.clone(),
+            "status" => status_or_dash(self.status),
+            "upstream" => dash_if_empty(&self.upstream),
+            "latency_ms" => self.latency.as_millis().to_string(),
+            "latency_us" => self.latency.as_micros().to_string(),
+            "request_bytes" => self.request_bytes.to_string(),
+            "response_bytes" => self.response_bytes.to_string(),
+            "api_name" => dash_if_empty(&self.api_name),
+            "api_version" => self.api_version.to_string(),
+            "user_agent" => dash_if_empty(&self.user_agent),
+            "referer" => dash_if_empty(&self.referer),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    pub fn to_json(&self) -> String {
+        json!({
+            "requestId": self.request_id,
+            "clientIp": self.client_ip,
+            "time": DateTime::from(self.time).to_iso8601(),
+            "method": self.method,
+            "uri": self.uri,
+            "path": self.path(),
+            "query": self.query(),
+            "protocol": self.protocol,
+            "status": self.status,
+            "upstream": self.upstream,
+            "latencyMs": self.latency.as_millis() as u64,
+            "requestBytes": self.request_bytes,
+            "responseBytes": self.response_bytes,
+            "apiName": self.api_name,
+            "apiVersion": self.api_version,
+            "userAgent": self.user_agent,
+            "referer": self.referer,
+        })
+        .to_string()
+    }
+}
+
+fn dash_if_empty(value: &str) -> String {
+    if value.is_empty() {
+        String::from("-")
+    } else {
+        value.to_string()
+    }
+}
+
+fn status_or_dash(status: u16) -> String {
+    if status == 0 {
+        String::from("-")
+    } else {
+        status.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Text(String),
+    Variable(String),
+}
+
+/// format of access log (SystemConfig::access_log_format)
+///  - "combined" (default): apache combined log format
+///  - "combined_id": apache combined log format with the request id at the end
+///  - "json": a json object per line
+///  - the others: template with variables (ex. "{client_ip} {method} {path} {status}")
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessLogFormat {
+    Json,
+    Template(Vec<Token>),
+}
+
+impl Default for AccessLogFormat {
+    fn default() -> Self {
+        AccessLogFormat::parse(COMBINED_FORMAT)
+    }
+}
+
+impl AccessLogFormat {
+    pub fn parse(format: &str) -> Self {
+        match format.trim() {
+            "" | "combined" | "apache" => AccessLogFormat::parse(COMBINED_FORMAT),
+            "combined_id" => AccessLogFormat::parse(COMBINED_ID_FORMAT),
+            "json" => AccessLogFormat::Json,
+            template => AccessLogFormat::Template(parse_template(template)),
+        }
+    }
+
+    pub fn render(&self, record: &AccessLogRecord) -> String {
+        match self {
+            AccessLogFormat::Json => record.to_json(),
+            AccessLogFormat::Template(tokens) => {
+                let mut line = String::new();
+                for token in tokens {
+                    match token {
+                        Token::Text(text) => line.push_str(text),
+                        Token::Variable(name) => match record.variable(name) {
+                            Some(value) => line.push_str(&value),
+                            None => {
+                                // unknown variable is written as it is
+                                line.push('{');
+                                line.push_str(name);
+                                line.push('}');
+                            }
+                        },
+                    }
+                }
+                line
+            }
+        }
+    }
+}
+
+fn parse_template(template: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        if start > 0 {
+            tokens.push(Token::Text(rest[..start].to_string()));
+        }
+        tokens.push(Token::Variable(rest[start + 1..end].trim().to_string()));
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest.to_string()));
+    }
+    tokens
+}
+
+/// change the format of access log (pushed by admin)
+pub fn set_format(format: &str) {
+    let format = AccessLogFormat::parse(format);
+    *ACCESS_LOG_FORMAT.write().unwrap() = Arc::new(format);
+}
+
+fn get_format() -> Arc<AccessLogFormat> {
+    ACCESS_LOG_FORMAT.read().unwrap().clone()
+}
+
+/// the record in the current format
+pub fn render(record: &AccessLogRecord) -> String {
+    get_format().render(record)
+}
+
+/// number of access logs dropped by each destination
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessLogDrops {
+    pub writer: usize,
+    pub syslog: usize,
+    pub http: usize,
+}
+
+/// dropped access logs since the last call
+pub fn take_drops() -> AccessLogDrops {
+    AccessLogDrops {
+        writer: DROPPED_COUNT.swap(0, Ordering::Relaxed),
+        syslog: ship::take_syslog_dropped(),
+        http: ship::take_http_dropped(),
+    }
+}
+
+/// the dropped access logs were not reported, report them next time
+pub fn requeue_drops(drops: &AccessLogDrops) {
+    DROPPED_COUNT.fetch_add(drops.writer, Ordering::Relaxed);
+    ship::requeue_syslog_dropped(drops.syslog);
+    ship::requeue_http_dropped(drops.http);
+}
+
+/// queue the record to the writer (never blocks the request)
+pub fn write(record: AccessLogRecord) {
+    ship::send(&record);
+
+    let sender = ACCESS_LOG_SENDER.read().unwrap();
+    if let Some(sender) = sender.as_ref() {
+        if let Err(TrySendError::Full(_)) = sender.try_send(record) {
+            DROPPED_COUNT.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// destination of access log
+enum Output {
+    Stdout(io::Stdout),
+    File(RotatingFile),
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::File(file) => file.flush(),
+        }
+    }
+}
+
+/// start the writer thread: write to the file, or stdout if the path is None
+pub fn handle(path: Option<String>, policy: RotatePolicy) -> Result<(), String> {
+    let output = match path {
+        Some(path) => {
+            Output::File(RotatingFile::open(&path, policy).map_err(|e| format!("{}: {}", path, e))?)
+        }
+        None => Output::Stdout(io::stdout()),
+    };
+
+    let (sender, receiver) = mpsc::sync_channel::<AccessLogRecord>(QUEUE_SIZE);
+    *ACCESS_LOG_SENDER.write().unwrap() = Some(sender);
+
+    thread::Builder::new()
+        .name(String::from("access-log"))
+        .spawn(move || {
+            let mut writer = BufWriter::new(output);
+            let mut flushed = Instant::now();
+            loop {
+                match receiver.recv_timeout(FLUSH_INTERVAL) {
+                    Ok(record) => {
+                        // a line is written at once, so it's not split by rotation
+                        let mut line = render(&record);
+                        line.push('\n');
+                        if let Err(e) = writer.write_all(line.as_bytes()) {
+                            error!("failed to write access log: {}", e);
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if flushed.elapsed() >= FLUSH_INTERVAL {
+                    let _ = writer.flush();
+                    flushed = Instant::now();
+
+                    if let Output::File(file) = writer.get_mut() {
+                        if let Err(e) = file.reopen_if_requested() {
+                            error!("failed to reopen access log: {}", e);
+                        }
+                    }
+                }
+            }
+            let _ = writer.flush();
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
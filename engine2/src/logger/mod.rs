@@ -0,0 +1,5 @@
+pub mod access;
+pub mod rotate;
+pub mod ship;
+pub mod system;
+pub mod time;
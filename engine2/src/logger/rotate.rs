@@ -0,0 +1,290 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_rotate.rs"]
+mod test_rotate;
+
+use super::time::DateTime;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{error, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// set by SIGUSR1 (ex. after logrotate moved the file)
+static REOPEN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotateInterval {
+    Hourly,
+    Daily,
+}
+
+impl RotateInterval {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "hourly" => Ok(RotateInterval::Hourly),
+            "daily" => Ok(RotateInterval::Daily),
+            _ => Err(String::from("Invalid rotate interval (hourly, daily)")),
+        }
+    }
+
+    fn secs(&self) -> u64 {
+        match self {
+            RotateInterval::Hourly => 3600,
+            RotateInterval::Daily => 86400,
+        }
+    }
+}
+
+/// when to rotate the log file and how to keep the rotated files
+#[derive(Debug, Clone, Default)]
+pub struct RotatePolicy {
+    /// bytes of the file
+    pub max_size: Option<u64>,
+    pub interval: Option<RotateInterval>,
+    /// gzip the rotated files
+    pub compress: bool,
+    /// number of the rotated files to keep (None: keep all)
+    pub retention: Option<usize>,
+}
+
+/// log file which is rotated by size or time
+pub struct RotatingFile {
+    path: PathBuf,
+    policy: RotatePolicy,
+    file: File,
+    size: u64,
+    // unix time to rotate the file next
+    next_rotation: Option<u64>,
+    // rotated files to compress and clean up, one at a time
+    worker: Option<Sender<PathBuf>>,
+}
+
+impl RotatingFile {
+    pub fn open(path: &str, policy: RotatePolicy) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        let next_rotation = policy
+            .interval
+            .map(|interval| next_boundary(now(), interval));
+
+        Ok(RotatingFile {
+            path,
+            policy,
+            file,
+            size,
+            next_rotation,
+            worker: None,
+        })
+    }
+
+    /// open the file again (the file may be moved by logrotate)
+    pub fn reopen(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file = open_append(&self.path)?;
+        self.size = self.file.metadata()?.len();
+        Ok(())
+    }
+
+    /// reopen if SIGUSR1 has been received
+    pub fn reopen_if_requested(&mut self) -> io::Result<()> {
+        if REOPEN.swap(false, Ordering::SeqCst) {
+            self.reopen()?;
+        }
+        Ok(())
+    }
+
+    fn should_rotate(&self, additional: u64) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let over_size = matches!(self.policy.max_size, Some(max) if self.size + additional > max);
+        let over_time = matches!(self.next_rotation, Some(next) if now() >= next);
+        over_size || over_time
+    }
+
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let rotated = rotated_path(&self.path, SystemTime::now());
+        fs::rename(&self.path, &rotated)?;
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        if let Some(interval) = self.policy.interval {
+            self.next_rotation = Some(next_boundary(now(), interval));
+        }
+
+        // compress and clean up in background, not to delay the writer
+        if self.policy.compress || self.policy.retention.is_some() {
+            let worker = self
+                .worker
+                .get_or_insert_with(|| spawn_worker(self.path.clone(), self.policy.clone()));
+            if worker.send(rotated).is_err() {
+                warn!("rotated file of {} is not cleaned up", self.path.display());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// compress and clean up the rotated files in order: a file is not removed while it's compressed
+// (the worker ends when the file is dropped)
+fn spawn_worker(path: PathBuf, policy: RotatePolicy) -> Sender<PathBuf> {
+    let (sender, receiver) = mpsc::channel::<PathBuf>();
+    thread::spawn(move || {
+        for rotated in receiver {
+            if policy.compress {
+                if let Err(e) = compress(&rotated) {
+                    warn!("failed to compress {}: {}", rotated.display(), e);
+                }
+            }
+            if let Some(retention) = policy.retention {
+                if let Err(e) = remove_old_files(&path, retention) {
+                    warn!("failed to remove old logs of {}: {}", path.display(), e);
+                }
+            }
+        }
+    });
+    sender
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.should_rotate(buf.len() as u64) {
+            if let Err(e) = self.rotate() {
+                error!("failed to rotate {}: {}", self.path.display(), e);
+            }
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// reopen the log files on SIGUSR1
+#[cfg(unix)]
+pub fn handle_reopen_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::task::spawn(async move {
+        let mut signal = match signal(SignalKind::user_defined1()) {
+            Ok(signal) => signal,
+            Err(e) => {
+                warn!("failed to listen SIGUSR1: {}", e);
+                return;
+            }
+        };
+        while signal.recv().await.is_some() {
+            REOPEN.store(true, Ordering::SeqCst);
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn handle_reopen_signal() {}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// next hour or day (UTC)
+fn next_boundary(now: u64, interval: RotateInterval) -> u64 {
+    (now / interval.secs() + 1) * interval.secs()
+}
+
+// access.log -> access.log.20220519-083015 (access.log.20220519-083015-1 if exists)
+fn rotated_path(path: &Path, time: SystemTime) -> PathBuf {
+    let time = DateTime::from(time);
+    let base = format!(
+        "{}.{:04}{:02}{:02}-{:02}{:02}{:02}",
+        path.display(),
+        time.year,
+        time.month,
+        time.day,
+        time.hour,
+        time.minute,
+        time.second
+    );
+
+    let mut rotated = PathBuf::from(&base);
+    let mut seq = 1;
+    while rotated.exists() || PathBuf::from(format!("{}.gz", rotated.display())).exists() {
+        rotated = PathBuf::from(format!("{}-{}", base, seq));
+        seq += 1;
+    }
+    rotated
+}
+
+fn compress(path: &Path) -> io::Result<()> {
+    let gz_path = PathBuf::from(format!("{}.gz", path.display()));
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(&gz_path)?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+/// remove the oldest rotated files except the last `retention` files
+pub fn remove_old_files(path: &Path, retention: usize) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let prefix = format!(
+        "{}.",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+
+    let mut rotated: Vec<((u64, u64), PathBuf)> = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let order = rotated_order(entry.file_name().to_string_lossy().strip_prefix(&prefix)?)?;
+            Some((order, entry.path()))
+        })
+        .collect();
+    // oldest first
+    rotated.sort();
+
+    let remove_count = rotated.len().saturating_sub(retention);
+    for (_, old) in rotated.into_iter().take(remove_count) {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+// "20220519-083015-2.gz" -> (20220519083015, 2), None if it's not a rotated file
+fn rotated_order(suffix: &str) -> Option<(u64, u64)> {
+    let suffix = suffix.strip_suffix(".gz").unwrap_or(suffix);
+    let (date, rest) = suffix.split_once('-')?;
+    let (time, seq) = match rest.split_once('-') {
+        Some((time, seq)) => (time, seq.parse().ok()?),
+        None => (rest, 0),
+    };
+    let digits = |s: &str, len: usize| s.len() == len && s.bytes().all(|c| c.is_ascii_digit());
+    if !digits(date, 8) || !digits(time, 6) {
+        return None;
+    }
+    Some((format!("{}{}", date, time).parse().ok()?, seq))
+}
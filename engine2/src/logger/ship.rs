@@ -0,0 +1,351 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_ship.rs"]
+mod test_ship;
+
+use super::access::{self, AccessLogRecord};
+use super::time::DateTime;
+use crate::tls::tls_connector::make_http_or_https_client;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request};
+use hyper_rustls::HttpsConnector;
+use lazy_static::lazy_static;
+use log::warn;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{lookup_host, TcpStream, UdpSocket};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio::time;
+
+// records waiting for each remote sink, the others are dropped
+const QUEUE_SIZE: usize = 8192;
+const BATCH_SIZE: usize = 500;
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+// facility local0, severity informational
+const SYSLOG_PRIORITY: u8 = 16 * 8 + 6;
+const SYSLOG_APP_NAME: &str = "osori";
+
+lazy_static! {
+    static ref SHIPPERS: RwLock<Vec<Shipper>> = RwLock::new(Vec::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogProtocol {
+    Udp,
+    Tcp,
+}
+
+/// syslog server to ship access log (udp://host:port, tcp://host:port)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyslogTarget {
+    pub protocol: SyslogProtocol,
+    pub address: String,
+}
+
+impl SyslogTarget {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (protocol, address) = match s.split_once("://") {
+            Some(("udp", address)) => (SyslogProtocol::Udp, address),
+            Some(("tcp", address)) => (SyslogProtocol::Tcp, address),
+            Some(_) => return Err(String::from("Invalid syslog protocol (udp, tcp)")),
+            None => (SyslogProtocol::Udp, s),
+        };
+        match address.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                Ok(SyslogTarget {
+                    protocol,
+                    address: address.to_string(),
+                })
+            }
+            _ => Err(String::from(
+                "Invalid syslog address (ex. udp://127.0.0.1:514)",
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SinkKind {
+    Syslog,
+    Http,
+}
+
+// queue of a remote sink
+struct Shipper {
+    kind: SinkKind,
+    sender: Sender<AccessLogRecord>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl Shipper {
+    fn send(&self, record: &AccessLogRecord) {
+        // never waits for the sink
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(record.clone()) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+enum Sink {
+    Syslog(SyslogSink),
+    Http(HttpSink),
+}
+
+impl Sink {
+    async fn send(&mut self, batch: &[AccessLogRecord]) -> Result<(), String> {
+        match self {
+            Sink::Syslog(sink) => sink.send(batch).await,
+            Sink::Http(sink) => sink.send(batch).await,
+        }
+    }
+
+    // forget the connection which may be broken in the middle of a message
+    fn reset(&mut self) {
+        if let Sink::Syslog(sink) = self {
+            sink.tcp = None;
+        }
+    }
+}
+
+struct SyslogSink {
+    target: SyslogTarget,
+    hostname: String,
+    udp: Option<UdpSocket>,
+    tcp: Option<TcpStream>,
+}
+
+impl SyslogSink {
+    fn new(target: SyslogTarget) -> Self {
+        use sysinfo::{System, SystemExt};
+
+        let hostname = System::new()
+            .host_name()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| String::from("-"));
+        SyslogSink {
+            target,
+            hostname,
+            udp: None,
+            tcp: None,
+        }
+    }
+
+    async fn send(&mut self, batch: &[AccessLogRecord]) -> Result<(), String> {
+        let messages = batch
+            .iter()
+            .map(|record| make_syslog_message(&self.hostname, record));
+
+        match self.target.protocol {
+            SyslogProtocol::Udp => {
+                if self.udp.is_none() {
+                    self.udp = Some(connect_udp(&self.target.address).await?);
+                }
+                let socket = self.udp.as_ref().unwrap();
+                for message in messages {
+                    // a datagram per message
+                    socket
+                        .send(message.as_bytes())
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+            SyslogProtocol::Tcp => {
+                if self.tcp.is_none() {
+                    let stream = TcpStream::connect(&self.target.address)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    self.tcp = Some(stream);
+                }
+                // octet counting framing (RFC 6587)
+                let mut frames = String::new();
+                for message in messages {
+                    frames.push_str(&format!("{} {}", message.len(), message));
+                }
+                let stream = self.tcp.as_mut().unwrap();
+                if let Err(e) = stream.write_all(frames.as_bytes()).await {
+                    // connect again for the next batch
+                    self.tcp = None;
+                    return Err(e.to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn connect_udp(address: &str) -> Result<UdpSocket, String> {
+    let remote = lookup_host(address)
+        .await
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("failed to resolve {}", address))?;
+    let local: SocketAddr = if remote.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(local).await.map_err(|e| e.to_string())?;
+    socket.connect(remote).await.map_err(|e| e.to_string())?;
+    Ok(socket)
+}
+
+// <134>1 2022-05-19T08:30:15.250Z host osori 1234 access - message (RFC 5424)
+fn make_syslog_message(hostname: &str, record: &AccessLogRecord) -> String {
+    format!(
+        "<{}>1 {} {} {} {} access - {}",
+        SYSLOG_PRIORITY,
+        DateTime::from(record.time).to_iso8601(),
+        hostname,
+        SYSLOG_APP_NAME,
+        std::process::id(),
+        access::render(record)
+    )
+}
+
+struct HttpSink {
+    url: String,
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+}
+
+impl HttpSink {
+    fn new(url: String) -> Self {
+        HttpSink {
+            url,
+            client: make_http_or_https_client::<Body>(),
+        }
+    }
+
+    // a json object per line
+    async fn send(&mut self, batch: &[AccessLogRecord]) -> Result<(), String> {
+        let mut body = String::new();
+        for record in batch {
+            body.push_str(&record.to_json());
+            body.push('\n');
+        }
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(&self.url)
+            .header("content-type", "application/x-ndjson")
+            .body(Body::from(body))
+            .map_err(|e| e.to_string())?;
+
+        let resp = self.client.request(req).await.map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("collector responded {}", resp.status()));
+        }
+        Ok(())
+    }
+}
+
+/// start to ship access log to the remote sinks
+pub fn handle(syslog: Option<SyslogTarget>, http: Option<String>) {
+    let mut shippers = SHIPPERS.write().unwrap();
+    if let Some(target) = syslog {
+        shippers.push(spawn(
+            SinkKind::Syslog,
+            Sink::Syslog(SyslogSink::new(target)),
+        ));
+    }
+    if let Some(url) = http {
+        shippers.push(spawn(SinkKind::Http, Sink::Http(HttpSink::new(url))));
+    }
+}
+
+fn spawn(kind: SinkKind, sink: Sink) -> Shipper {
+    let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+    let dropped = Arc::new(AtomicUsize::new(0));
+    tokio::spawn(run(sink, receiver, dropped.clone()));
+
+    Shipper {
+        kind,
+        sender,
+        dropped,
+    }
+}
+
+// send the records in batches, the batch is dropped if the sink is unavailable
+async fn run(mut sink: Sink, mut receiver: Receiver<AccessLogRecord>, dropped: Arc<AtomicUsize>) {
+    let mut closed = false;
+    while !closed {
+        let mut batch = Vec::new();
+        let deadline = time::sleep(BATCH_INTERVAL);
+        tokio::pin!(deadline);
+        while batch.len() < BATCH_SIZE {
+            tokio::select! {
+                record = receiver.recv() => match record {
+                    Some(record) => batch.push(record),
+                    None => {
+                        closed = true;
+                        break;
+                    }
+                },
+                _ = &mut deadline => break,
+            }
+        }
+        if batch.is_empty() {
+            continue;
+        }
+
+        let result = match time::timeout(SEND_TIMEOUT, sink.send(&batch)).await {
+            Ok(result) => result,
+            Err(_) => Err(String::from("timed out")),
+        };
+        if let Err(e) = result {
+            warn!("failed to ship {} access logs: {}", batch.len(), e);
+            sink.reset();
+            dropped.fetch_add(batch.len(), Ordering::Relaxed);
+        }
+    }
+}
+
+/// queue the record to every remote sink
+pub fn send(record: &AccessLogRecord) {
+    for shipper in SHIPPERS.read().unwrap().iter() {
+        shipper.send(record);
+    }
+}
+
+/// records dropped by the sink (full queue or failed delivery) since the last call
+fn take_dropped(kind: SinkKind) -> usize {
+    SHIPPERS
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|shipper| shipper.kind == kind)
+        .map(|shipper| shipper.dropped.swap(0, Ordering::Relaxed))
+        .sum()
+}
+
+// dropped logs which were not reported (the poll failed)
+fn add_dropped(kind: SinkKind, count: usize) {
+    if let Some(shipper) = SHIPPERS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|shipper| shipper.kind == kind)
+    {
+        shipper.dropped.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+pub fn requeue_syslog_dropped(count: usize) {
+    add_dropped(SinkKind::Syslog, count);
+}
+
+pub fn requeue_http_dropped(count: usize) {
+    add_dropped(SinkKind::Http, count);
+}
+
+pub fn take_syslog_dropped() -> usize {
+    take_dropped(SinkKind::Syslog)
+}
+
+pub fn take_http_dropped() -> usize {
+    take_dropped(SinkKind::Http)
+}
@@ -0,0 +1,208 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_system.rs"]
+mod test_system;
+
+use super::time::DateTime;
+use lazy_static::lazy_static;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+// targets are module paths of this crate (ex. engine2::service::proxy)
+const CRATE_NAME: &str = env!("CARGO_CRATE_NAME");
+
+lazy_static! {
+    static ref LOGGER: SystemLogger = SystemLogger {
+        filter: RwLock::new(Arc::new(LogFilter::default())),
+        format: RwLock::new(SystemLogFormat::Text),
+        verbose: AtomicBool::new(false),
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemLogFormat {
+    Text,
+    Json,
+}
+
+impl SystemLogFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "text" => Ok(SystemLogFormat::Text),
+            "json" => Ok(SystemLogFormat::Json),
+            _ => Err(String::from("Invalid log format (text, json)")),
+        }
+    }
+}
+
+/// level of the system log (SystemConfig::system_log_level)
+///  - "info": every module
+///  - "warn,service::proxy=debug": modules can have their own level
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    // (module path, level), the longest path is applied
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter {
+            default: LevelFilter::Info,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl LogFilter {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut filter = LogFilter::default();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    let target = target.trim().trim_start_matches("crate::");
+                    let target = if target == CRATE_NAME
+                        || target.starts_with(&format!("{}::", CRATE_NAME))
+                    {
+                        target.to_string()
+                    } else {
+                        format!("{}::{}", CRATE_NAME, target)
+                    };
+                    filter.targets.push((target, parse_level(level)?));
+                }
+                None => filter.default = parse_level(directive)?,
+            }
+        }
+        filter
+            .targets
+            .sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+        Ok(filter)
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .find(|(path, _)| {
+                target == path
+                    || (target.starts_with(path.as_str()) && target[path.len()..].starts_with("::"))
+            })
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, std::cmp::max)
+    }
+
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        level <= self.level(target)
+    }
+}
+
+fn parse_level(s: &str) -> Result<LevelFilter, String> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "off" => Ok(LevelFilter::Off),
+        "error" => Ok(LevelFilter::Error),
+        "warn" | "warning" => Ok(LevelFilter::Warn),
+        "info" => Ok(LevelFilter::Info),
+        "debug" => Ok(LevelFilter::Debug),
+        "trace" => Ok(LevelFilter::Trace),
+        level => Err(format!("Invalid log level: {}", level)),
+    }
+}
+
+struct SystemLogger {
+    filter: RwLock<Arc<LogFilter>>,
+    format: RwLock<SystemLogFormat>,
+    // debug level of this crate is kept while booting in verbose mode
+    verbose: AtomicBool,
+}
+
+impl Log for SystemLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if self.verbose.load(Ordering::Relaxed)
+            && metadata.level() <= Level::Debug
+            && metadata.target().starts_with(CRATE_NAME)
+        {
+            return true;
+        }
+        self.filter
+            .read()
+            .unwrap()
+            .enabled(metadata.target(), metadata.level())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let format = *self.format.read().unwrap();
+        let mut line = render(format, SystemTime::now(), record);
+        line.push('\n');
+        // a line is written at once, so lines of threads are not mixed
+        let _ = io::stderr().lock().write_all(line.as_bytes());
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+// 2022-05-19T08:30:15.250Z INFO  engine2::service::route: message
+fn render(format: SystemLogFormat, time: SystemTime, record: &Record) -> String {
+    let time = DateTime::from(time).to_iso8601();
+    match format {
+        SystemLogFormat::Text => format!(
+            "{} {:<5} {}: {}",
+            time,
+            record.level(),
+            record.target(),
+            record.args()
+        ),
+        SystemLogFormat::Json => json!({
+            "time": time,
+            "level": record.level().as_str(),
+            "target": record.target(),
+            "message": record.args().to_string(),
+        })
+        .to_string(),
+    }
+}
+
+/// start the system logger (to stderr), verbose mode logs debug messages until boot_completed()
+pub fn init(level: &str, format: SystemLogFormat, verbose: bool) -> Result<(), String> {
+    *LOGGER.format.write().unwrap() = format;
+    LOGGER.verbose.store(verbose, Ordering::Relaxed);
+    set_level(level)?;
+    log::set_logger(&*LOGGER).map_err(|e| e.to_string())
+}
+
+/// change the level of the system log (pushed by admin)
+pub fn set_level(level: &str) -> Result<(), String> {
+    let filter = LogFilter::parse(level)?;
+    apply_max_level(&filter);
+    *LOGGER.filter.write().unwrap() = Arc::new(filter);
+    Ok(())
+}
+
+/// stop verbose diagnostics of booting
+pub fn boot_completed() {
+    LOGGER.verbose.store(false, Ordering::Relaxed);
+    apply_max_level(&LOGGER.filter.read().unwrap());
+}
+
+fn apply_max_level(filter: &LogFilter) {
+    let max_level = if LOGGER.verbose.load(Ordering::Relaxed) {
+        filter.max_level().max(LevelFilter::Debug)
+    } else {
+        filter.max_level()
+    };
+    log::set_max_level(max_level);
+}
@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod test_access {
+    use super::super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn make_record() -> AccessLogRecord {
+        AccessLogRecord {
+            request_id: String::from("req-1"),
+            client_ip: String::from("10.0.0.1"),
+            // 2022-05-19 08:30:15.250 UTC
+            time: UNIX_EPOCH + Duration::from_millis(1652949015250),
+            method: String::from("GET"),
+            uri: String::from("/v1/test?page=2"),
+            protocol: String::from("HTTP/1.1"),
+            status: 200,
+            upstream: String::from("https://httpbin.org/get"),
+            latency: Duration::from_millis(42),
+            request_bytes: 0,
+            response_bytes: 512,
+            api_name: String::from("test"),
+            api_version: 1,
+            user_agent: String::from("curl/7.68.0"),
+            referer: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_combined_format() {
+        let format = AccessLogFormat::parse("combined");
+        assert_eq!(
+            format.render(&make_record()),
+            r#"10.0.0.1 - - [19/May/2022:08:30:15 +0000] "GET /v1/test?page=2 HTTP/1.1" 200 512 "-" "curl/7.68.0""#
+        );
+        assert_eq!(AccessLogFormat::parse(""), format);
+
+        let format = AccessLogFormat::parse("combined_id");
+        assert!(format
+            .render(&make_record())
+            .ends_with(r#""curl/7.68.0" "req-1""#));
+    }
+
+    #[test]
+    fn test_template_format() {
+        let format = AccessLogFormat::parse(
+            "{time_iso8601} {api_name}:{api_version} {path} {status} {latency_ms}ms {unknown}",
+        );
+        assert_eq!(
+            format.render(&make_record()),
+            "2022-05-19T08:30:15.250Z test:1 /v1/test 200 42ms {unknown}"
+        );
+    }
+
+    #[test]
+    fn test_json_format() {
+        let format = AccessLogFormat::parse("json");
+        let line: serde_json::Value = serde_json::from_str(&format.render(&make_record())).unwrap();
+        assert_eq!(line["query"], "page=2");
+        assert_eq!(line["upstream"], "https://httpbin.org/get");
+        assert_eq!(line["latencyMs"], 42);
+    }
+}
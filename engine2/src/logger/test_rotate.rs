@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod test_rotate {
+    use super::super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use std::time::Duration;
+
+    fn make_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("osori-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn rotated_files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name != "access.log")
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_next_boundary() {
+        assert_eq!(next_boundary(86400 + 10, RotateInterval::Daily), 86400 * 2);
+        assert_eq!(next_boundary(3600 * 5, RotateInterval::Hourly), 3600 * 6);
+    }
+
+    #[test]
+    fn test_rotated_path() {
+        let time = UNIX_EPOCH + Duration::from_secs(1652949015);
+        let path = rotated_path(Path::new("/tmp/none/access.log"), time);
+        assert_eq!(path, PathBuf::from("/tmp/none/access.log.20220519-083015"));
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = make_dir("rotate");
+        let path = dir.join("access.log");
+        let policy = RotatePolicy {
+            max_size: Some(10),
+            ..Default::default()
+        };
+        let mut file = RotatingFile::open(path.to_str().unwrap(), policy).unwrap();
+
+        file.write_all(b"12345678\n").unwrap();
+        file.write_all(b"abcdefgh\n").unwrap();
+        file.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "abcdefgh\n");
+        let rotated = rotated_files(&dir);
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(
+            fs::read_to_string(dir.join(&rotated[0])).unwrap(),
+            "12345678\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotated_order() {
+        let mut names = vec![
+            "20220519-083015-10",
+            "20220519-083015.gz",
+            "20220519-083015-2.gz",
+            "20220519-083015-1",
+            "20220518-235959",
+        ];
+        names.sort_by_key(|name| rotated_order(name));
+        assert_eq!(
+            names,
+            vec![
+                "20220518-235959",
+                "20220519-083015.gz",
+                "20220519-083015-1",
+                "20220519-083015-2.gz",
+                "20220519-083015-10",
+            ]
+        );
+        assert_eq!(rotated_order("backup"), None);
+        assert_eq!(rotated_order("2022-01-01"), None);
+    }
+
+    #[test]
+    fn test_compress_and_retention() {
+        let dir = make_dir("retention");
+        let path = dir.join("access.log");
+        for name in [
+            "access.log.20220101-000000",
+            "access.log.20220102-000000.gz",
+        ] {
+            fs::write(dir.join(name), "old\n").unwrap();
+        }
+        let rotated = dir.join("access.log.20220103-000000");
+        fs::write(&rotated, "new\n").unwrap();
+
+        compress(&rotated).unwrap();
+        remove_old_files(&path, 2).unwrap();
+
+        assert_eq!(
+            rotated_files(&dir),
+            vec![
+                "access.log.20220102-000000.gz".to_string(),
+                "access.log.20220103-000000.gz".to_string()
+            ]
+        );
+        let mut content = String::new();
+        GzDecoder::new(File::open(dir.join("access.log.20220103-000000.gz")).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "new\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
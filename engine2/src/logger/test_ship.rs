@@ -0,0 +1,143 @@
+#[cfg(test)]
+mod test_ship {
+    use super::super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{body, Response, Server};
+    use std::convert::Infallible;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    fn make_record(uri: &str) -> AccessLogRecord {
+        AccessLogRecord {
+            request_id: String::from("req-1"),
+            client_ip: String::from("10.0.0.1"),
+            time: UNIX_EPOCH + Duration::from_millis(1652949015250),
+            method: String::from("GET"),
+            uri: uri.to_string(),
+            protocol: String::from("HTTP/1.1"),
+            status: 200,
+            upstream: String::new(),
+            latency: Duration::from_millis(42),
+            request_bytes: 0,
+            response_bytes: 512,
+            api_name: String::from("test"),
+            api_version: 1,
+            user_agent: String::new(),
+            referer: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_syslog_target() {
+        let target = SyslogTarget::parse("tcp://127.0.0.1:514").unwrap();
+        assert_eq!(target.protocol, SyslogProtocol::Tcp);
+        assert_eq!(target.address, "127.0.0.1:514");
+
+        let target = SyslogTarget::parse("logs.local:514").unwrap();
+        assert_eq!(target.protocol, SyslogProtocol::Udp);
+
+        assert!(SyslogTarget::parse("http://127.0.0.1:514").is_err());
+        assert!(SyslogTarget::parse("udp://127.0.0.1").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_syslog_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = SyslogTarget {
+            protocol: SyslogProtocol::Udp,
+            address: server.local_addr().unwrap().to_string(),
+        };
+        let shipper = spawn(SinkKind::Syslog, Sink::Syslog(SyslogSink::new(target)));
+        shipper.send(&make_record("/v1/first"));
+        shipper.send(&make_record("/v1/second"));
+
+        let mut buf = [0u8; 2048];
+        for uri in ["/v1/first", "/v1/second"] {
+            let len = time::timeout(Duration::from_secs(5), server.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            let message = String::from_utf8_lossy(&buf[..len]).to_string();
+            assert!(message.starts_with("<134>1 2022-05-19T08:30:15.250Z "));
+            assert!(message.contains(" osori "));
+            assert!(message.contains(&format!("\"GET {} HTTP/1.1\" 200", uri)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_syslog_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = SyslogTarget {
+            protocol: SyslogProtocol::Tcp,
+            address: listener.local_addr().unwrap().to_string(),
+        };
+        let shipper = spawn(SinkKind::Syslog, Sink::Syslog(SyslogSink::new(target)));
+        shipper.send(&make_record("/v1/test"));
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = vec![0u8; 2048];
+        let len = time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        let frame = String::from_utf8_lossy(&buf[..len]).to_string();
+        let (length, message) = frame.split_once(' ').unwrap();
+        assert_eq!(length.parse::<usize>().unwrap(), message.len());
+        assert!(message.starts_with("<134>1 "));
+    }
+
+    #[tokio::test]
+    async fn test_http_batch() {
+        let (body_sender, mut body_receiver) = mpsc::channel::<String>(1);
+        let make_service = make_service_fn(move |_| {
+            let body_sender = body_sender.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let body_sender = body_sender.clone();
+                    async move {
+                        let bytes = body::to_bytes(req.into_body()).await.unwrap();
+                        let _ = body_sender
+                            .send(String::from_utf8_lossy(&bytes).to_string())
+                            .await;
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}/logs", server.local_addr());
+        tokio::spawn(server);
+
+        let shipper = spawn(SinkKind::Http, Sink::Http(HttpSink::new(url)));
+        shipper.send(&make_record("/v1/first"));
+        shipper.send(&make_record("/v1/second"));
+
+        let body = time::timeout(Duration::from_secs(5), body_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let lines: Vec<serde_json::Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["uri"], "/v1/first");
+        assert_eq!(lines[1]["uri"], "/v1/second");
+        assert_eq!(shipper.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drop_unavailable() {
+        // nobody listens on the port
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/logs", listener.local_addr().unwrap());
+        drop(listener);
+
+        let shipper = spawn(SinkKind::Http, Sink::Http(HttpSink::new(url)));
+        shipper.send(&make_record("/v1/test"));
+
+        time::sleep(BATCH_INTERVAL * 2).await;
+        assert_eq!(shipper.dropped.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod test_system {
+    use super::super::*;
+
+    #[test]
+    fn test_parse_filter() {
+        let filter = LogFilter::parse("WARN, service=debug, service::proxy=error").unwrap();
+        assert!(filter.enabled("engine2::config::api", Level::Warn));
+        assert!(!filter.enabled("engine2::config::api", Level::Info));
+        assert!(filter.enabled("engine2::service::route", Level::Debug));
+        assert!(!filter.enabled("engine2::service::proxy", Level::Warn));
+        // not a child module
+        assert!(!filter.enabled("engine2::service_x", Level::Info));
+        assert_eq!(filter.max_level(), LevelFilter::Debug);
+
+        assert_eq!(LogFilter::parse("").unwrap(), LogFilter::default());
+        assert!(LogFilter::parse("verbose").is_err());
+        assert!(LogFilter::parse("service=loud").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_millis(1652949015250);
+        let args = format_args!("hello \"osori\"");
+        let record = Record::builder()
+            .args(args)
+            .level(Level::Info)
+            .target("engine2::service::route")
+            .build();
+
+        assert_eq!(
+            render(SystemLogFormat::Text, time, &record),
+            r#"2022-05-19T08:30:15.250Z INFO  engine2::service::route: hello "osori""#
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&render(SystemLogFormat::Json, time, &record)).unwrap();
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["target"], "engine2::service::route");
+        assert_eq!(json["message"], r#"hello "osori""#);
+    }
+}
@@ -1,16 +1,27 @@
 mod admin;
 mod config;
+mod logger;
 mod monitor;
 mod service;
 mod tls;
+mod trace;
 
+use crate::service::client_ip::{self, ClientAddr};
+use crate::service::concurrency::ConcurrencyLayer;
 use crate::service::cors::CorsLayer;
+use crate::service::ip_filter::IpFilterLayer;
 use crate::service::proxy::ProxyService;
-use hyper::Server;
-use service::access_log::{AccessLogLayer, AccessLogRequestBody};
-use service::route::{dummy_route, RouteLayer};
-use tls::tls_connector::make_http_or_https_client;
-use tower::make::Shared;
+use crate::service::rate_limit::{self, RateLimitLayer};
+use crate::service::rate_limit_sync;
+use crate::service::size_limit::SizeLimitLayer;
+use crate::service::trace::TraceLayer;
+use hyper::server::conn::AddrStream;
+use hyper::service::make_service_fn;
+use hyper::{Body, Request, Server};
+use log::{debug, error, info};
+use service::access_log::AccessLogLayer;
+use service::route::RouteLayer;
+use std::convert::Infallible;
 use tower::ServiceBuilder;
 
 #[tokio::main]
@@ -18,34 +29,141 @@ async fn main() {
     let config = match config::args::parse() {
         Ok(config) => config,
         Err(e) => {
-            println!("error occurred: {}", e);
+            // the logger is not ready yet
+            eprintln!("error occurred: {}", e);
             std::process::exit(-1);
         }
     };
 
-    // register to admin
-    if let Err(e) = admin::register::handle(config).await {
-        println!("error occurred: {}", e);
+    // start system log
+    if let Err(e) = logger::system::init(&config.log_level, config.log_format, config.verbose) {
+        eprintln!("error occurred: {}", e);
         std::process::exit(-1);
+    }
+    debug!(
+        "admin address: {}",
+        config
+            .admin_address
+            .iter()
+            .map(|address| address.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    debug!(
+        "config file: {:?}, snapshot: {:?}",
+        config.config_file, config.snapshot
+    );
+    debug!(
+        "engine: {:?}, group: {:?}",
+        config.engine_name, config.group_name
+    );
+    debug!(
+        "limits: concurrency={:?}, shed cpu={:?}, size={:?}",
+        config.concurrency_limit, config.shed_cpu_usage, config.size_limit
+    );
+    debug!(
+        "ip filter: {:?}, trusted proxies: {:?} ({:?})",
+        config.ip_filter, config.trusted_proxies, config.forwarded_header
+    );
+    debug!(
+        "access log: {:?} (rotate: {:?}), syslog: {:?}, http: {:?}",
+        config.access_log,
+        config.access_log_rotate,
+        config.access_log_syslog,
+        config.access_log_http
+    );
+    debug!(
+        "metrics: {:?}, status: {:?}, trace: {:?}",
+        config.metrics_address, config.status_address, config.trace
+    );
+
+    // start to write access log
+    if let Err(e) =
+        logger::access::handle(config.access_log.clone(), config.access_log_rotate.clone())
+    {
+        error!("failed to open access log: {}", e);
+        std::process::exit(-1);
+    }
+    logger::rotate::handle_reopen_signal();
+    logger::ship::handle(
+        config.access_log_syslog.clone(),
+        config.access_log_http.clone(),
+    );
+
+    let metrics_address = config.metrics_address;
+    let status_address = config.status_address;
+    let trace_config = config.trace.clone();
+    let rate_limit_redis = config.rate_limit_redis.clone();
+    let group_name = config.group_name.clone().unwrap_or_default();
+    let size_limit_layer = SizeLimitLayer::global(config.size_limit.clone());
+    let ip_filter_layer = IpFilterLayer::global(config.ip_filter.clone());
+    client_ip::set_trusted_proxies(config.trusted_proxies.clone(), config.forwarded_header);
+    let concurrency_layer =
+        ConcurrencyLayer::new(config.concurrency_limit.clone(), config.shed_cpu_usage);
+
+    // clean up unused rate limit counters
+    rate_limit::handle();
+    // share counters of cluster-wide rate limits in the group
+    // (before registering: the poll loop sends them unless redis is used)
+    rate_limit_sync::handle(rate_limit_redis, group_name);
+
+    if let Some(path) = config.config_file.clone() {
+        // standalone: config and apis from the local file
+        if let Err(e) = config::file::handle(path) {
+            error!("failed to load config file: {}", e);
+            std::process::exit(-1);
+        }
     } else {
-        println!("Success to register!");
+        // register to admin
+        // (or boot from the snapshot if admin is unreachable)
+        admin::snapshot::set_path(config.snapshot.clone());
+        if let Err(e) = admin::register::handle(config).await {
+            error!("failed to register: {}", e);
+            std::process::exit(-1);
+        }
     }
 
+    // cpu usage for load shedding
+    monitor::load::handle();
+    // prometheus metrics
+    monitor::metrics::handle(metrics_address);
+    // engine status for the operator on the host
+    monitor::status::handle(status_address);
+    // export spans to the OTLP collector
+    trace::handle(trace_config);
+
     // ip address for http service
     let http_addr = ([127, 0, 0, 1], 3000).into();
 
-    let service = ServiceBuilder::new()
-        .layer(AccessLogLayer::new())
-        .layer(RouteLayer::new(dummy_route()))
-        .layer(CorsLayer)
-        .service(ProxyService);
+    let make_service = make_service_fn(|conn: &AddrStream| {
+        let client_addr = ClientAddr(conn.remote_addr());
+        let service = ServiceBuilder::new()
+            .map_request(move |mut req: Request<Body>| {
+                req.extensions_mut().insert(client_addr);
+                req
+            })
+            .layer(AccessLogLayer::new())
+            .layer(TraceLayer)
+            // before the route: unknown paths are rejected the same way
+            .layer(ip_filter_layer.clone())
+            .layer(size_limit_layer.clone())
+            .layer(RouteLayer::new())
+            .layer(IpFilterLayer::api())
+            .layer(SizeLimitLayer::api())
+            .layer(RateLimitLayer::new())
+            .layer(concurrency_layer.clone())
+            .layer(CorsLayer)
+            .service(ProxyService);
+        async move { Ok::<_, Infallible>(service) }
+    });
 
     // http server
-    let http_server = Server::bind(&http_addr).serve(Shared::new(service));
+    let http_server = Server::bind(&http_addr).serve(make_service);
 
-    println!("Listening on http://{}", http_addr);
+    info!("Listening on http://{}", http_addr);
+    logger::system::boot_completed();
 
     if let Err(e) = http_server.await {
-        eprintln!("server error: {}", e);
+        error!("server error: {}", e);
     }
 }
@@ -0,0 +1,142 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_latency.rs"]
+mod test_latency;
+
+use dashmap::DashMap;
+use hdrhistogram::Histogram;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// 1 us ~ 1 hour (microseconds), 2 significant digits
+const MAX_LATENCY: u64 = 3_600_000_000;
+const SIGNIFICANT_DIGITS: u8 = 2;
+
+lazy_static! {
+    // (api name, api version), since the last poll
+    static ref API_LATENCIES: DashMap<(String, usize), ApiHistograms> = DashMap::new();
+}
+
+struct ApiHistograms {
+    total: Histogram<u64>,
+    upstream: Histogram<u64>,
+    gateway: Histogram<u64>,
+}
+
+impl ApiHistograms {
+    fn new() -> Self {
+        let make = || Histogram::new_with_bounds(1, MAX_LATENCY, SIGNIFICANT_DIGITS).unwrap();
+        ApiHistograms {
+            total: make(),
+            upstream: make(),
+            gateway: make(),
+        }
+    }
+}
+
+/// latency distribution in microseconds
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl Percentiles {
+    fn from(histogram: &Histogram<u64>) -> Self {
+        if histogram.is_empty() {
+            return Percentiles::default();
+        }
+        Percentiles {
+            p50: histogram.value_at_quantile(0.5),
+            p90: histogram.value_at_quantile(0.9),
+            p99: histogram.value_at_quantile(0.99),
+            max: histogram.max(),
+        }
+    }
+}
+
+/// latency of an api since the last poll
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiLatency {
+    pub api_name: String,
+    pub api_version: usize,
+    pub count: u64,
+    /// from the request to the end of the response
+    pub total: Percentiles,
+    /// from sending to the upstream to its response header
+    pub upstream: Percentiles,
+    /// total - upstream (the requests sent to the upstream)
+    pub gateway: Percentiles,
+}
+
+fn micros(duration: Duration) -> u64 {
+    (duration.as_micros() as u64).max(1)
+}
+
+/// a request of the api is finished (upstream is None if it's not sent to the upstream)
+pub fn record(api_name: &str, api_version: usize, total: Duration, upstream: Option<Duration>) {
+    let mut histograms = API_LATENCIES
+        .entry((api_name.to_string(), api_version))
+        .or_insert_with(ApiHistograms::new);
+    histograms.total.saturating_record(micros(total));
+    if let Some(upstream) = upstream {
+        histograms.upstream.saturating_record(micros(upstream));
+        histograms
+            .gateway
+            .saturating_record(micros(total.saturating_sub(upstream)));
+    }
+}
+
+/// histograms of each api taken for the poll
+pub struct TakenLatencies {
+    histograms: Vec<((String, usize), ApiHistograms)>,
+}
+
+impl TakenLatencies {
+    /// percentiles of each api, sorted by the name and version
+    pub fn report(&self) -> Vec<ApiLatency> {
+        let mut latencies: Vec<ApiLatency> = self
+            .histograms
+            .iter()
+            .map(|((api_name, api_version), histograms)| ApiLatency {
+                api_name: api_name.clone(),
+                api_version: *api_version,
+                count: histograms.total.len(),
+                total: Percentiles::from(&histograms.total),
+                upstream: Percentiles::from(&histograms.upstream),
+                gateway: Percentiles::from(&histograms.gateway),
+            })
+            .collect();
+        latencies.sort_by(|a, b| (&a.api_name, a.api_version).cmp(&(&b.api_name, b.api_version)));
+        latencies
+    }
+}
+
+/// latencies of each api since the last call
+pub fn take_latencies() -> TakenLatencies {
+    let keys: Vec<(String, usize)> = API_LATENCIES
+        .iter()
+        .map(|entry| entry.key().clone())
+        .collect();
+    TakenLatencies {
+        histograms: keys
+            .into_iter()
+            .filter_map(|key| API_LATENCIES.remove(&key))
+            .collect(),
+    }
+}
+
+/// the latencies were not reported: merged into the ones recorded after taken
+pub fn requeue_latencies(taken: TakenLatencies) {
+    for (key, taken) in taken.histograms {
+        let mut histograms = API_LATENCIES.entry(key).or_insert_with(ApiHistograms::new);
+        // same bounds, never fails
+        let _ = histograms.total.add(&taken.total);
+        let _ = histograms.upstream.add(&taken.upstream);
+        let _ = histograms.gateway.add(&taken.gateway);
+    }
+}
@@ -0,0 +1,28 @@
+use super::system::get_cpu_usage;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use sysinfo::{System, SystemExt};
+use tokio::{task, time};
+
+// cpu usage (f32 bits) refreshed every second
+static CPU_USAGE: AtomicU32 = AtomicU32::new(0);
+
+/// start to refresh the cpu usage of the engine's host every second
+pub fn handle() {
+    task::spawn(async move {
+        let mut my_system = System::new();
+        let mut interval = time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+
+            my_system.refresh_cpu();
+            let cpu_usage = get_cpu_usage(&my_system);
+            CPU_USAGE.store(cpu_usage.to_bits(), Ordering::Relaxed);
+        }
+    });
+}
+
+/// used cpu % at the last refresh
+pub fn get_current_cpu_usage() -> f32 {
+    f32::from_bits(CPU_USAGE.load(Ordering::Relaxed))
+}
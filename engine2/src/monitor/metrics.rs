@@ -0,0 +1,326 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_metrics.rs"]
+mod test_metrics;
+
+use super::load::get_current_cpu_usage;
+use super::stats;
+use super::system::{get_memory_usage, get_network_total};
+use dashmap::DashMap;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use lazy_static::lazy_static;
+use log::{error, info};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use sysinfo::{System, SystemExt};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// upper bounds of the latency buckets (seconds)
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// (api name, api version)
+type ApiKey = (String, usize);
+
+lazy_static! {
+    static ref REQUESTS: DashMap<(String, usize, u16), AtomicU64> = DashMap::new();
+    static ref LATENCIES: DashMap<ApiKey, Histogram> = DashMap::new();
+    static ref UPSTREAM_ERRORS: DashMap<String, AtomicU64> = DashMap::new();
+    static ref HOST: Mutex<System> = Mutex::new(System::new());
+}
+
+static DROPPED_SPANS: AtomicU64 = AtomicU64::new(0);
+static TLS_HANDSHAKE_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Default)]
+struct Histogram {
+    // not cumulative, the last one is +Inf
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        let index = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn api_label(name: &str) -> String {
+    if name.is_empty() {
+        String::from("-")
+    } else {
+        name.to_string()
+    }
+}
+
+/// a request is finished (api name is empty if it's not routed)
+pub fn record_request(api_name: &str, api_version: usize, status: u16, latency: Duration) {
+    let api_name = api_label(api_name);
+    REQUESTS
+        .entry((api_name.clone(), api_version, status))
+        .or_default()
+        .fetch_add(1, Ordering::Relaxed);
+    LATENCIES
+        .entry((api_name, api_version))
+        .or_default()
+        .observe(latency);
+}
+
+/// the upstream of the api failed (connection, protocol)
+pub fn count_upstream_error(api_name: &str) {
+    UPSTREAM_ERRORS
+        .entry(api_label(api_name))
+        .or_default()
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+/// spans not sent to the trace collector (queue full or export failed)
+pub fn count_dropped_spans(count: usize) {
+    DROPPED_SPANS.fetch_add(count as u64, Ordering::Relaxed);
+}
+
+/// tls handshake of the https listener failed
+pub fn count_tls_handshake_failure() {
+    TLS_HANDSHAKE_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+// "\", """ and new line are escaped in label values
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn render_requests(out: &mut String) {
+    write_header(
+        out,
+        "osori_requests_total",
+        "counter",
+        "Requests handled by the engine.",
+    );
+    let mut requests: Vec<_> = REQUESTS
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.load(Ordering::Relaxed)))
+        .collect();
+    requests.sort();
+    for ((api, version, code), count) in requests {
+        let _ = writeln!(
+            out,
+            "osori_requests_total{{api=\"{}\",version=\"{}\",code=\"{}\"}} {}",
+            escape(&api),
+            version,
+            code,
+            count
+        );
+    }
+}
+
+fn render_latencies(out: &mut String) {
+    let name = "osori_request_duration_seconds";
+    write_header(
+        out,
+        name,
+        "histogram",
+        "Time from the request to the end of the response.",
+    );
+    let mut keys: Vec<ApiKey> = LATENCIES.iter().map(|entry| entry.key().clone()).collect();
+    keys.sort();
+    for key in keys {
+        let histogram = match LATENCIES.get(&key) {
+            Some(histogram) => histogram,
+            None => continue,
+        };
+        let labels = format!("api=\"{}\",version=\"{}\"", escape(&key.0), key.1);
+
+        let mut cumulative = 0;
+        for (i, count) in histogram.buckets.iter().enumerate() {
+            cumulative += count.load(Ordering::Relaxed);
+            let bound = match LATENCY_BUCKETS.get(i) {
+                Some(bound) => bound.to_string(),
+                None => String::from("+Inf"),
+            };
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            );
+        }
+        let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, cumulative);
+    }
+}
+
+fn render_upstream_errors(out: &mut String) {
+    write_header(
+        out,
+        "osori_upstream_errors_total",
+        "counter",
+        "Requests failed to get the response of the upstream.",
+    );
+    let mut errors: Vec<_> = UPSTREAM_ERRORS
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.load(Ordering::Relaxed)))
+        .collect();
+    errors.sort();
+    for (api, count) in errors {
+        let _ = writeln!(
+            out,
+            "osori_upstream_errors_total{{api=\"{}\"}} {}",
+            escape(&api),
+            count
+        );
+    }
+}
+
+fn render_value(out: &mut String, name: &str, kind: &str, help: &str, value: impl ToString) {
+    write_header(out, name, kind, help);
+    let _ = writeln!(out, "{} {}", name, value.to_string());
+}
+
+fn render_host(out: &mut String) {
+    let mut host = HOST.lock().unwrap();
+    host.refresh_memory();
+    host.refresh_networks_list();
+    host.refresh_networks();
+    let (used_memory, total_memory) = get_memory_usage(&host);
+    let (received, transmitted) = get_network_total(&host);
+
+    render_value(
+        out,
+        "osori_host_cpu_usage_percent",
+        "gauge",
+        "Used cpu of the host.",
+        get_current_cpu_usage(),
+    );
+    render_value(
+        out,
+        "osori_host_memory_used_bytes",
+        "gauge",
+        "Used memory of the host.",
+        used_memory * 1024,
+    );
+    render_value(
+        out,
+        "osori_host_memory_total_bytes",
+        "gauge",
+        "Total memory of the host.",
+        total_memory * 1024,
+    );
+    render_value(
+        out,
+        "osori_host_network_received_bytes_total",
+        "counter",
+        "Bytes received by the network interfaces of the host.",
+        received,
+    );
+    render_value(
+        out,
+        "osori_host_network_transmitted_bytes_total",
+        "counter",
+        "Bytes transmitted by the network interfaces of the host.",
+        transmitted,
+    );
+}
+
+/// metrics in the prometheus text exposition format
+pub fn render() -> String {
+    let mut out = String::new();
+    render_requests(&mut out);
+    render_latencies(&mut out);
+    render_upstream_errors(&mut out);
+    render_value(
+        &mut out,
+        "osori_active_connections",
+        "gauge",
+        "Open client connections.",
+        stats::get_connection_count(),
+    );
+    render_value(
+        &mut out,
+        "osori_active_requests",
+        "gauge",
+        "Requests which are not finished.",
+        stats::get_active_request_count(),
+    );
+    render_value(
+        &mut out,
+        "osori_trace_dropped_spans_total",
+        "counter",
+        "Spans not sent to the trace collector.",
+        DROPPED_SPANS.load(Ordering::Relaxed),
+    );
+    // 0 until the https listener is served
+    render_value(
+        &mut out,
+        "osori_tls_handshake_failures_total",
+        "counter",
+        "Failed tls handshakes of the https listener.",
+        TLS_HANDSHAKE_FAILURES.load(Ordering::Relaxed),
+    );
+    render_host(&mut out);
+    out
+}
+
+async fn serve_metrics(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("content-type", CONTENT_TYPE)
+            .body(Body::from(
+                // sysinfo refresh of the host blocks
+                tokio::task::spawn_blocking(render)
+                    .await
+                    .unwrap_or_default(),
+            )),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    Ok(response.unwrap())
+}
+
+/// start the metrics listener (GET /metrics)
+pub fn handle(address: Option<SocketAddr>) {
+    let address = match address {
+        Some(address) => address,
+        None => return,
+    };
+
+    tokio::spawn(async move {
+        let make_service =
+            make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(serve_metrics)) });
+        let server = match Server::try_bind(&address) {
+            Ok(builder) => builder.serve(make_service),
+            Err(e) => {
+                error!("failed to listen metrics on {}: {}", address, e);
+                return;
+            }
+        };
+        info!("Metrics on http://{}/metrics", address);
+        if let Err(e) = server.await {
+            error!("metrics server error: {}", e);
+        }
+    });
+}
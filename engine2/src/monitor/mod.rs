@@ -1 +1,7 @@
+pub mod latency;
+pub mod load;
+pub mod metrics;
+pub mod stats;
+pub mod status;
 pub mod system;
+pub mod upstream;
@@ -0,0 +1,166 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_stats.rs"]
+mod test_stats;
+
+use crate::service::access_log::LogContext;
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// open client connections
+static CONNECTION_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// since the last poll
+static REQUEST_COUNT: AtomicUsize = AtomicUsize::new(0);
+static RESPONSE_COUNT: AtomicUsize = AtomicUsize::new(0);
+static RESPONSE_TIME_TOTAL: AtomicU64 = AtomicU64::new(0);
+static RESPONSE_STATUS: [AtomicUsize; 5] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref ACTIVE_REQUESTS: DashMap<u64, ActiveRequest> = DashMap::new();
+}
+
+/// traffic since the last call of take_traffic()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Traffic {
+    pub client_count: usize,
+    pub request_count: usize,
+    pub response_count: usize,
+    /// average in milliseconds
+    pub response_time: usize,
+    /// sum in microseconds
+    pub response_time_total: u64,
+    /// 1xx, 2xx, 3xx, 4xx, 5xx
+    pub response_status: Vec<usize>,
+}
+
+struct ActiveRequest {
+    start: Instant,
+    context: LogContext,
+}
+
+/// request which is not finished yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveRequestInfo {
+    pub api_name: String,
+    pub api_version: usize,
+    pub elapsed: Duration,
+}
+
+/// counted while the connection is open
+pub struct ConnectionGuard;
+
+impl ConnectionGuard {
+    pub fn open() -> Self {
+        CONNECTION_COUNT.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        CONNECTION_COUNT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// listed in the active requests until it's dropped
+pub struct RequestGuard {
+    id: u64,
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        ACTIVE_REQUESTS.remove(&self.id);
+    }
+}
+
+/// a request started (api of the context is filled by the route layer later)
+pub fn start_request(context: LogContext) -> RequestGuard {
+    REQUEST_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    let id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+    ACTIVE_REQUESTS.insert(
+        id,
+        ActiveRequest {
+            start: Instant::now(),
+            context,
+        },
+    );
+    RequestGuard { id }
+}
+
+/// a response is sent (status 0: no response, ex. upstream error)
+pub fn finish_request(status: u16, latency: Duration) {
+    if status == 0 {
+        return;
+    }
+    RESPONSE_COUNT.fetch_add(1, Ordering::Relaxed);
+    RESPONSE_TIME_TOTAL.fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    if let Some(count) = RESPONSE_STATUS.get(status as usize / 100 - 1) {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn take_traffic() -> Traffic {
+    let response_count = RESPONSE_COUNT.swap(0, Ordering::Relaxed);
+    let response_time_total = RESPONSE_TIME_TOTAL.swap(0, Ordering::Relaxed);
+    let response_time = if response_count == 0 {
+        0
+    } else {
+        (response_time_total / response_count as u64 / 1000) as usize
+    };
+
+    Traffic {
+        client_count: CONNECTION_COUNT.load(Ordering::Relaxed),
+        request_count: REQUEST_COUNT.swap(0, Ordering::Relaxed),
+        response_count,
+        response_time,
+        response_time_total,
+        response_status: RESPONSE_STATUS
+            .iter()
+            .map(|count| count.swap(0, Ordering::Relaxed))
+            .collect(),
+    }
+}
+
+/// the traffic was not reported, report it next time
+pub fn requeue_traffic(traffic: &Traffic) {
+    REQUEST_COUNT.fetch_add(traffic.request_count, Ordering::Relaxed);
+    RESPONSE_COUNT.fetch_add(traffic.response_count, Ordering::Relaxed);
+    RESPONSE_TIME_TOTAL.fetch_add(traffic.response_time_total, Ordering::Relaxed);
+    for (count, taken) in RESPONSE_STATUS.iter().zip(&traffic.response_status) {
+        count.fetch_add(*taken, Ordering::Relaxed);
+    }
+}
+
+pub fn get_connection_count() -> usize {
+    CONNECTION_COUNT.load(Ordering::Relaxed)
+}
+
+pub fn get_active_request_count() -> usize {
+    ACTIVE_REQUESTS.len()
+}
+
+pub fn active_requests() -> Vec<ActiveRequestInfo> {
+    ACTIVE_REQUESTS
+        .iter()
+        .map(|entry| {
+            let (api_name, api_version) = entry.context.api();
+            ActiveRequestInfo {
+                api_name,
+                api_version,
+                elapsed: entry.start.elapsed(),
+            }
+        })
+        .collect()
+}
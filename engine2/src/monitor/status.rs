@@ -0,0 +1,79 @@
+use super::upstream;
+use crate::admin::{poll, register};
+use crate::config::{api, system, validate};
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+/// status of the engine as json
+pub fn render() -> Value {
+    let upstreams: serde_json::Map<String, Value> = upstream::get_upstreams()
+        .into_iter()
+        .map(|(server, health)| (server, json!(health)))
+        .collect();
+
+    json!({
+        "version": {
+            "build": env!("CARGO_PKG_VERSION"),
+            "engine": register::ENGINE_VERSION,
+        },
+        "engineId": register::get_engine_id(),
+        "admin": poll::get_connection(),
+        "lastPoll": poll::get_last_poll(),
+        "config": system::get_current().map(|config| config.to_redacted_json()),
+        "apiMap": api::dump_current_map(),
+        "apiRejects": validate::get_rejects(),
+        "upstreams": upstreams,
+    })
+}
+
+async fn serve_status(peer: SocketAddr, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    // the listener is on loopback, but check the peer again
+    if !peer.ip().is_loopback() {
+        warn!("status request from {} is rejected", peer);
+        let response = Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::empty());
+        return Ok(response.unwrap());
+    }
+
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/status") => Response::builder()
+            .header("content-type", "application/json")
+            .body(Body::from(render().to_string())),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    Ok(response.unwrap())
+}
+
+/// start the status listener (GET /status)
+pub fn handle(address: Option<SocketAddr>) {
+    let address = match address {
+        Some(address) => address,
+        None => return,
+    };
+
+    tokio::spawn(async move {
+        let make_service = make_service_fn(|conn: &AddrStream| {
+            let peer = conn.remote_addr();
+            async move { Ok::<_, Infallible>(service_fn(move |req| serve_status(peer, req))) }
+        });
+        let server = match Server::try_bind(&address) {
+            Ok(builder) => builder.serve(make_service),
+            Err(e) => {
+                error!("failed to listen status on {}: {}", address, e);
+                return;
+            }
+        };
+        info!("Status on http://{}/status", address);
+        if let Err(e) = server.await {
+            error!("status server error: {}", e);
+        }
+    });
+}
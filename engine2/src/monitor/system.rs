@@ -1,4 +1,5 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 #[path = "test_system.rs"]
 mod test_system;
 
@@ -29,6 +30,18 @@ pub fn get_network_usage(s: &System) -> (u64, u64) {
     (network_in, network_out)
 }
 
+/// (network input, output) bytes since the interfaces are up
+pub fn get_network_total(s: &System) -> (u64, u64) {
+    let mut network_in = 0;
+    let mut network_out = 0;
+    for (_, data) in s.networks() {
+        network_in += data.total_received();
+        network_out += data.total_transmitted();
+    }
+
+    (network_in, network_out)
+}
+
 /// used cpu % since the last refresh
 pub fn get_cpu_usage(s: &System) -> f32 {
     s.global_processor_info().cpu_usage()
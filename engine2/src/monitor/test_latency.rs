@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod test_latency {
+    use super::super::*;
+
+    #[test]
+    fn test_take_latencies() {
+        for ms in 1..=100 {
+            record(
+                "latency-test",
+                1,
+                Duration::from_millis(ms + 2),
+                Some(Duration::from_millis(ms)),
+            );
+        }
+        // not sent to the upstream (ex. rejected)
+        record("latency-test", 2, Duration::from_micros(300), None);
+
+        let latencies: Vec<ApiLatency> = take_latencies()
+            .report()
+            .into_iter()
+            .filter(|latency| latency.api_name == "latency-test")
+            .collect();
+        assert_eq!(latencies.len(), 2);
+
+        let v1 = &latencies[0];
+        assert_eq!(v1.api_version, 1);
+        assert_eq!(v1.count, 100);
+        // 2 significant digits
+        let near = |value: u64, expected: u64| value.abs_diff(expected) <= expected / 50;
+        assert!(near(v1.upstream.p50, 50_000), "{:?}", v1.upstream);
+        assert!(near(v1.upstream.p90, 90_000), "{:?}", v1.upstream);
+        assert!(near(v1.upstream.p99, 99_000), "{:?}", v1.upstream);
+        assert!(near(v1.total.max, 102_000), "{:?}", v1.total);
+        assert!(near(v1.gateway.p50, 2_000), "{:?}", v1.gateway);
+        assert!(near(v1.gateway.max, 2_000), "{:?}", v1.gateway);
+
+        let v2 = &latencies[1];
+        assert_eq!(v2.count, 1);
+        assert!(near(v2.total.p50, 300));
+        assert_eq!(v2.upstream, Percentiles::default());
+
+        // reset after taken
+        assert!(take_latencies()
+            .report()
+            .iter()
+            .all(|latency| latency.api_name != "latency-test"));
+    }
+
+    #[test]
+    fn test_requeue_latencies() {
+        record("requeue-test", 1, Duration::from_millis(10), None);
+        let taken = take_latencies();
+        record("requeue-test", 1, Duration::from_millis(30), None);
+
+        // merged with the one recorded after taken
+        requeue_latencies(taken);
+        let latencies: Vec<ApiLatency> = take_latencies()
+            .report()
+            .into_iter()
+            .filter(|latency| latency.api_name == "requeue-test")
+            .collect();
+        assert_eq!(latencies.len(), 1);
+        assert_eq!(latencies[0].count, 2);
+    }
+}
@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod test_metrics {
+    use super::super::*;
+
+    #[test]
+    fn test_render() {
+        record_request("metrics-test", 1, 200, Duration::from_millis(3));
+        record_request("metrics-test", 1, 200, Duration::from_millis(300));
+        record_request("metrics-test", 1, 502, Duration::from_secs(20));
+        count_upstream_error("metrics-test");
+        count_dropped_spans(2);
+
+        let out = render();
+        assert!(out.contains("# TYPE osori_requests_total counter\n"));
+        assert!(out
+            .contains("osori_requests_total{api=\"metrics-test\",version=\"1\",code=\"200\"} 2\n"));
+        assert!(out
+            .contains("osori_requests_total{api=\"metrics-test\",version=\"1\",code=\"502\"} 1\n"));
+
+        let labels = "api=\"metrics-test\",version=\"1\"";
+        for (le, count) in [
+            ("0.005", 1),
+            ("0.25", 1),
+            ("0.5", 2),
+            ("10", 2),
+            ("+Inf", 3),
+        ] {
+            let line = format!(
+                "osori_request_duration_seconds_bucket{{{},le=\"{}\"}} {}\n",
+                labels, le, count
+            );
+            assert!(out.contains(&line), "{}", line);
+        }
+        assert!(out.contains(&format!(
+            "osori_request_duration_seconds_sum{{{}}} 20.303\n",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "osori_request_duration_seconds_count{{{}}} 3\n",
+            labels
+        )));
+        assert!(out.contains("osori_upstream_errors_total{api=\"metrics-test\"} 1\n"));
+        assert!(out.contains("# TYPE osori_trace_dropped_spans_total counter\n"));
+        assert!(out.contains("# TYPE osori_tls_handshake_failures_total counter\n"));
+        assert!(out.contains("# TYPE osori_host_memory_total_bytes gauge\n"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -0,0 +1,57 @@
+#[cfg(test)]
+mod test_stats {
+    use super::super::*;
+    use crate::service::access_log::{update_log_context, LogContext};
+    use http::Request;
+
+    #[test]
+    fn test_traffic() {
+        let connection = ConnectionGuard::open();
+
+        let context = LogContext::default();
+        let mut req = Request::new(());
+        req.extensions_mut().insert(context.clone());
+        let first = start_request(context);
+        let second = start_request(LogContext::default());
+        update_log_context(&req, |info| {
+            info.api_name = String::from("test");
+            info.api_version = 2;
+        });
+
+        let mut active = active_requests();
+        active.sort_by(|a, b| b.api_name.cmp(&a.api_name));
+        assert_eq!(active.len(), 2);
+        assert_eq!(active[0].api_name, "test");
+        assert_eq!(active[0].api_version, 2);
+
+        finish_request(200, Duration::from_millis(10));
+        drop(first);
+        finish_request(503, Duration::from_millis(30));
+        drop(second);
+        // no response
+        finish_request(0, Duration::from_millis(50));
+
+        assert!(active_requests().is_empty());
+        let traffic = take_traffic();
+        assert_eq!(
+            traffic,
+            Traffic {
+                client_count: 1,
+                request_count: 2,
+                response_count: 2,
+                response_time: 20,
+                response_time_total: 40_000,
+                response_status: vec![0, 1, 0, 0, 1],
+            }
+        );
+        // not reported: taken again with the next poll
+        requeue_traffic(&traffic);
+        assert_eq!(take_traffic(), traffic);
+
+        drop(connection);
+        let traffic = take_traffic();
+        assert_eq!(traffic.client_count, 0);
+        assert_eq!(traffic.request_count, 0);
+        assert_eq!(traffic.response_status, vec![0; 5]);
+    }
+}
@@ -14,6 +14,6 @@ mod test_system {
     fn test_get_hostname() {
         let mut test_system = System::new_all();
         test_system.refresh_all();
-        assert!(get_hostname(&test_system).len() > 0);
+        assert!(!get_hostname(&test_system).is_empty());
     }
 }
@@ -0,0 +1,52 @@
+use crate::logger::time::DateTime;
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::time::SystemTime;
+
+lazy_static! {
+    // target server -> health seen by the proxied requests
+    static ref UPSTREAMS: DashMap<String, UpstreamHealth> = DashMap::new();
+}
+
+/// passive health of an upstream (no probe is sent)
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamHealth {
+    pub success_count: u64,
+    pub failure_count: u64,
+    pub consecutive_failures: u64,
+    pub last_success: Option<String>,
+    pub last_failure: Option<String>,
+    pub last_error: Option<String>,
+}
+
+fn now() -> String {
+    DateTime::from(SystemTime::now()).to_iso8601()
+}
+
+pub fn record_success(upstream: &str) {
+    let mut health = UPSTREAMS.entry(upstream.to_string()).or_default();
+    health.success_count += 1;
+    health.consecutive_failures = 0;
+    health.last_success = Some(now());
+}
+
+/// connection error or 5xx of the upstream
+pub fn record_failure(upstream: &str, error: String) {
+    let mut health = UPSTREAMS.entry(upstream.to_string()).or_default();
+    health.failure_count += 1;
+    health.consecutive_failures += 1;
+    health.last_failure = Some(now());
+    health.last_error = Some(error);
+}
+
+/// (target server, health) sorted by the server
+pub fn get_upstreams() -> Vec<(String, UpstreamHealth)> {
+    let mut upstreams: Vec<(String, UpstreamHealth)> = UPSTREAMS
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+    upstreams.sort_by(|a, b| a.0.cmp(&b.0));
+    upstreams
+}
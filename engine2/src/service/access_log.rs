@@ -1,10 +1,20 @@
+use crate::logger::access::{self, AccessLogRecord};
+use crate::monitor::stats::{self, ConnectionGuard, RequestGuard};
+use crate::monitor::{latency, metrics};
+use crate::service::client_ip::client_ip;
+use crate::service::request_id::{self, RequestId, X_REQUEST_ID};
 use bytes::Buf;
 use futures_util::ready;
-use http::{HeaderMap, Request, Response};
+use futures_util::task::AtomicWaker;
+use http::header::{HeaderName, REFERER, USER_AGENT};
+use http::{HeaderMap, HeaderValue, Request, Response};
 use http_body::Body;
 use pin_project::pin_project;
-use std::sync::atomic::{AtomicI64, Ordering};
-use std::sync::Arc;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use std::{
     future::Future,
     pin::Pin,
@@ -30,16 +40,22 @@ impl<S> Layer<S> for AccessLogLayer {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// service of a connection, the connection is counted while it's alive
+#[derive(Clone)]
 pub struct AccessLog<S> {
     inner: S,
+    _connection: Arc<ConnectionGuard>,
 }
 
 impl<S> AccessLog<S> {
     pub fn new(inner: S) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            _connection: Arc::new(ConnectionGuard::open()),
+        }
     }
 
+    #[allow(dead_code)]
     pub fn layer() -> AccessLogLayer {
         AccessLogLayer::new()
     }
@@ -57,17 +73,42 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let request_id = request_id::assign(&mut req);
         let request_size = Arc::new(AtomicI64::new(0));
+        let context = LogContext::default();
+        req.extensions_mut().insert(context.clone());
+
+        let metric = Metric {
+            _active: stats::start_request(context.clone()),
+            request_id,
+            start: Instant::now(),
+            time: SystemTime::now(),
+            client_ip: client_ip(&req)
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|| String::from("-")),
+            method: req.method().to_string(),
+            uri: req
+                .uri()
+                .path_and_query()
+                .map(|path| path.to_string())
+                .unwrap_or_default(),
+            protocol: format!("{:?}", req.version()),
+            user_agent: get_header(&req, USER_AGENT),
+            referer: get_header(&req, REFERER),
+            request_size: request_size.clone(),
+            status: 0,
+            response_size: 0,
+            context,
+        };
+
         ResponseFuture {
             inner: self.inner.call(req.map(|inner| AccessLogRequestBody {
                 inner,
                 request_size,
+                max_size: None,
             })),
-            metric: Some(Metric {
-                id: 1,
-                response_size: 0,
-            }),
+            metric: Some(metric),
         }
     }
 }
@@ -89,26 +130,84 @@ where
         let this = self.project();
         let mut response = ready!(this.inner.poll(cx)?);
         let mut metric = this.metric.take().unwrap();
+        metric.status = response.status().as_u16();
+        response.headers_mut().insert(
+            X_REQUEST_ID,
+            HeaderValue::from_str(&metric.request_id.0).unwrap(),
+        );
         Poll::Ready(Ok(
             response.map(|inner| AccessLogResponseBody { inner, metric })
         ))
     }
 }
 
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+/// error of the request body which is larger than the max body size of the api
+#[derive(Debug)]
+pub struct BodyTooLarge;
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "request body is too large")
+    }
+}
+
+impl Error for BodyTooLarge {}
+
+/// notified when the request body gets larger than the max body size
+/// (the body is streamed to the upstream by the connection task)
+#[derive(Default, Clone)]
+pub struct BodyTooLargeSignal {
+    inner: Arc<(AtomicBool, AtomicWaker)>,
+}
+
+impl BodyTooLargeSignal {
+    fn notify(&self) {
+        self.inner.0.store(true, Ordering::SeqCst);
+        self.inner.1.wake();
+    }
+
+    /// ready once the body is larger than the max body size
+    pub fn poll_too_large(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.1.register(cx.waker());
+        if self.is_too_large() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    pub fn is_too_large(&self) -> bool {
+        self.inner.0.load(Ordering::SeqCst)
+    }
+}
+
 #[pin_project]
 pub struct AccessLogRequestBody<B> {
     #[pin]
     pub inner: B,
     request_size: Arc<AtomicI64>,
+    max_size: Option<(u64, BodyTooLargeSignal)>,
+}
+
+impl<B> AccessLogRequestBody<B> {
+    /// fail the body while streaming if it's larger than max_size
+    pub fn set_max_size(&mut self, max_size: u64) -> BodyTooLargeSignal {
+        let signal = BodyTooLargeSignal::default();
+        self.max_size = Some((max_size, signal.clone()));
+        signal
+    }
 }
 
 impl<B> Body for AccessLogRequestBody<B>
 where
     B: Body,
+    B::Error: Into<BoxError>,
 {
     type Data = B::Data;
 
-    type Error = B::Error;
+    type Error = BoxError;
 
     fn poll_data(
         self: Pin<&mut Self>,
@@ -118,17 +217,25 @@ where
 
         let value = ready!(this.inner.poll_data(cx));
         if let Some(Ok(chunk)) = &value {
-            this.request_size
-                .fetch_add(chunk.remaining() as i64, Ordering::Relaxed);
+            let size = this
+                .request_size
+                .fetch_add(chunk.remaining() as i64, Ordering::Relaxed)
+                + chunk.remaining() as i64;
+            if let Some((max_size, signal)) = this.max_size {
+                if size as u64 > *max_size {
+                    signal.notify();
+                    return Poll::Ready(Some(Err(Box::new(BodyTooLarge))));
+                }
+            }
         }
-        Poll::Ready(value)
+        Poll::Ready(value.map(|value| value.map_err(Into::into)))
     }
 
     fn poll_trailers(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
-        self.project().inner.poll_trailers(cx)
+        self.project().inner.poll_trailers(cx).map_err(Into::into)
     }
 
     fn is_end_stream(&self) -> bool {
@@ -183,13 +290,96 @@ where
     }
 }
 
+/// information of the request filled by the inner layers (route, proxy)
+#[derive(Debug, Default)]
+pub struct RequestInfo {
+    pub api_name: String,
+    pub api_version: usize,
+    pub upstream: String,
+    /// until the response header of the upstream
+    pub upstream_latency: Option<Duration>,
+}
+
+/// shared with the inner layers through request extensions
+#[derive(Debug, Clone, Default)]
+pub struct LogContext(Arc<Mutex<RequestInfo>>);
+
+impl LogContext {
+    /// name and version of the routed api
+    pub fn api(&self) -> (String, usize) {
+        let info = self.0.lock().unwrap();
+        (info.api_name.clone(), info.api_version)
+    }
+
+    pub fn update(&self, update: impl FnOnce(&mut RequestInfo)) {
+        update(&mut self.0.lock().unwrap());
+    }
+}
+
+/// fill the information of the request for access log
+pub fn update_log_context<B>(req: &Request<B>, update: impl FnOnce(&mut RequestInfo)) {
+    if let Some(context) = req.extensions().get::<LogContext>() {
+        context.update(update);
+    }
+}
+
+fn get_header<B>(req: &Request<B>, name: HeaderName) -> String {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
 struct Metric {
-    id: i32,
+    _active: RequestGuard,
+    request_id: RequestId,
+    start: Instant,
+    time: SystemTime,
+    client_ip: String,
+    method: String,
+    uri: String,
+    protocol: String,
+    user_agent: String,
+    referer: String,
+    request_size: Arc<AtomicI64>,
+    status: u16,
     response_size: i64,
+    context: LogContext,
 }
 
 impl Drop for Metric {
     fn drop(&mut self) {
-        println!("request finished {} {}", self.id, self.response_size);
+        let info = std::mem::take(&mut *self.context.0.lock().unwrap());
+        let latency = self.start.elapsed();
+        stats::finish_request(self.status, latency);
+        if self.status != 0 {
+            metrics::record_request(&info.api_name, info.api_version, self.status, latency);
+            if !info.api_name.is_empty() {
+                latency::record(
+                    &info.api_name,
+                    info.api_version,
+                    latency,
+                    info.upstream_latency,
+                );
+            }
+        }
+        access::write(AccessLogRecord {
+            request_id: std::mem::take(&mut self.request_id.0),
+            client_ip: std::mem::take(&mut self.client_ip),
+            time: self.time,
+            method: std::mem::take(&mut self.method),
+            uri: std::mem::take(&mut self.uri),
+            protocol: std::mem::take(&mut self.protocol),
+            status: self.status,
+            upstream: info.upstream,
+            latency,
+            request_bytes: self.request_size.load(Ordering::Relaxed) as u64,
+            response_bytes: self.response_size as u64,
+            api_name: info.api_name,
+            api_version: info.api_version,
+            user_agent: std::mem::take(&mut self.user_agent),
+            referer: std::mem::take(&mut self.referer),
+        });
     }
 }
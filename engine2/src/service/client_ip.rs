@@ -0,0 +1,113 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_client_ip.rs"]
+mod test_client_ip;
+
+use crate::config::ip_filter::{Cidr, ForwardedHeader};
+use http::header::FORWARDED;
+use http::{HeaderMap, Request};
+use lazy_static::lazy_static;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::RwLock;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+// proxies in front of the engine which are trusted to append to the forwarded header
+lazy_static! {
+    static ref TRUSTED_PROXIES: RwLock<(Vec<Cidr>, ForwardedHeader)> =
+        RwLock::new((Vec::new(), ForwardedHeader::XForwardedFor));
+}
+
+pub fn set_trusted_proxies(proxies: Vec<Cidr>, header: ForwardedHeader) {
+    *TRUSTED_PROXIES.write().unwrap() = (proxies, header);
+}
+
+/// remote address of the connection (from `AddrStream::remote_addr`)
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub SocketAddr);
+
+/// ip address of the client which sent the request
+pub fn client_ip<B>(req: &Request<B>) -> Option<IpAddr> {
+    let remote = req.extensions().get::<ClientAddr>()?.0.ip();
+    let (trusted, header) = &*TRUSTED_PROXIES.read().unwrap();
+    Some(resolve_client_ip(remote, req.headers(), trusted, *header))
+}
+
+/// right-most address of the header which is not a trusted proxy
+/// (only the header the proxies append to, the client can send the other one)
+pub fn resolve_client_ip(
+    remote: IpAddr,
+    headers: &HeaderMap,
+    trusted: &[Cidr],
+    header: ForwardedHeader,
+) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|cidr| cidr.contains(ip));
+    if !is_trusted(&remote) {
+        return remote;
+    }
+
+    let hops = match header {
+        ForwardedHeader::XForwardedFor => get_x_forwarded_for(headers),
+        ForwardedHeader::Forwarded => get_forwarded_for(headers),
+    };
+
+    let mut client = remote;
+    for hop in hops.iter().rev() {
+        match hop {
+            Some(ip) => {
+                client = *ip;
+                if !is_trusted(ip) {
+                    break;
+                }
+            }
+            // unknown or obfuscated address: the last trusted one is used
+            None => break,
+        }
+    }
+    client
+}
+
+// X-Forwarded-For: client, proxy1, proxy2
+fn get_x_forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(parse_node)
+        .collect()
+}
+
+// Forwarded: for=192.0.2.60;proto=http, for="[2001:db8:cafe::17]:4711"
+fn get_forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all(FORWARDED)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                if key.trim().eq_ignore_ascii_case("for") {
+                    Some(parse_node(value.trim().trim_matches('"')))
+                } else {
+                    None
+                }
+            })
+        })
+        .collect()
+}
+
+// ip address with optional port (1.2.3.4, 1.2.3.4:80, [::1]:80, ::1)
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    node.strip_prefix('[')
+        .and_then(|node| node.strip_suffix(']'))
+        .and_then(|node| node.parse::<IpAddr>().ok())
+}
@@ -0,0 +1,257 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_concurrency.rs"]
+mod test_concurrency;
+
+use crate::config::api;
+use crate::config::limit::ConcurrencyLimit;
+use crate::monitor::load::get_current_cpu_usage;
+use crate::service::reject::reject;
+use crate::service::route::Route;
+use dashmap::DashMap;
+use http::{HeaderMap, Request, Response, StatusCode};
+use http_body::Body;
+use lazy_static::lazy_static;
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time;
+use tower_layer::Layer;
+use tower_service::Service;
+
+// (api id, api version)
+type ApiKey = (String, usize);
+
+// limiters are kept out of the api map, so in-flight counts survive map swaps
+lazy_static! {
+    static ref API_LIMITERS: DashMap<ApiKey, Arc<Limiter>> = DashMap::new();
+}
+
+// requests shed since the last poll
+static SHED_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// generation of the api map the limiters were cleaned up for
+static LIMITERS_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// number of shed requests since the last call (for the poll message)
+pub fn take_shed_count() -> usize {
+    SHED_COUNT.swap(0, Ordering::Relaxed)
+}
+
+/// the shed requests were not reported, report them next time
+pub fn requeue_shed_count(count: usize) {
+    SHED_COUNT.fetch_add(count, Ordering::Relaxed);
+}
+
+/// in-flight slots and the bounded wait queue
+#[derive(Debug)]
+pub struct Limiter {
+    limit: ConcurrencyLimit,
+    semaphore: Arc<Semaphore>,
+    queued: AtomicUsize,
+}
+
+impl Limiter {
+    pub fn new(limit: ConcurrencyLimit) -> Self {
+        Limiter {
+            semaphore: Arc::new(Semaphore::new(limit.max_concurrent)),
+            queued: AtomicUsize::new(0),
+            limit,
+        }
+    }
+
+    /// get a slot, or None if the request should be shed
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+
+        // wait in the queue
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.limit.max_queue {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        let acquire = self.semaphore.clone().acquire_owned();
+        let permit = if self.limit.queue_timeout > 0 {
+            let timeout = Duration::from_secs(self.limit.queue_timeout);
+            time::timeout(timeout, acquire).await.ok()
+        } else {
+            Some(acquire.await)
+        };
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+
+        permit.and_then(|permit| permit.ok())
+    }
+}
+
+// limiter of the api (recreated if admin changed the limit)
+fn get_api_limiter(api_id: &str, api_version: usize, limit: &ConcurrencyLimit) -> Arc<Limiter> {
+    retain_api_limiters();
+
+    // one limiter even if the first requests come at the same time
+    let mut limiter = API_LIMITERS
+        .entry((api_id.to_string(), api_version))
+        .or_insert_with(|| Arc::new(Limiter::new(limit.clone())));
+    if limiter.limit != *limit {
+        *limiter = Arc::new(Limiter::new(limit.clone()));
+    }
+    limiter.clone()
+}
+
+// in-flight counts of the removed apis are not needed anymore
+// (once after the map is replaced)
+fn retain_api_limiters() {
+    let generation = api::get_generation();
+    if LIMITERS_GENERATION.swap(generation, Ordering::SeqCst) == generation {
+        return;
+    }
+    let apis = api::get_api_keys();
+    API_LIMITERS.retain(|key, _| apis.contains(key));
+}
+
+/// response body holding the slots until the end of the body (or the body is dropped)
+#[pin_project]
+#[derive(Debug, Default)]
+pub struct ConcurrencyBody<B> {
+    #[pin]
+    inner: B,
+    permits: Vec<OwnedSemaphorePermit>,
+}
+
+impl<B> Body for ConcurrencyBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let value = this.inner.poll_data(cx);
+        if let Poll::Ready(None) | Poll::Ready(Some(Err(_))) = &value {
+            this.permits.clear();
+        }
+        value
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.project();
+        let value = this.inner.poll_trailers(cx);
+        if value.is_ready() {
+            this.permits.clear();
+        }
+        value
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLayer {
+    global: Option<Arc<Limiter>>,
+    shed_cpu_usage: Option<f32>,
+}
+
+impl ConcurrencyLayer {
+    /// global limit of the engine and cpu % to start shedding
+    pub fn new(global: Option<ConcurrencyLimit>, shed_cpu_usage: Option<f32>) -> Self {
+        ConcurrencyLayer {
+            global: global.map(|limit| Arc::new(Limiter::new(limit))),
+            shed_cpu_usage,
+        }
+    }
+}
+
+impl<S> Layer<S> for ConcurrencyLayer {
+    type Service = ConcurrencyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConcurrencyService {
+            inner,
+            global: self.global.clone(),
+            shed_cpu_usage: self.shed_cpu_usage,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConcurrencyService<S> {
+    inner: S,
+    global: Option<Arc<Limiter>>,
+    shed_cpu_usage: Option<f32>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ConcurrencyService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = Response<ConcurrencyBody<ResBody>>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        // shed immediately if the host is busy
+        if let Some(threshold) = self.shed_cpu_usage {
+            if get_current_cpu_usage() >= threshold {
+                SHED_COUNT.fetch_add(1, Ordering::Relaxed);
+                return Box::pin(async { Ok(reject(StatusCode::SERVICE_UNAVAILABLE)) });
+            }
+        }
+
+        let api_limiter = req.extensions().get::<Route>().and_then(|route| {
+            let de_api = &route.api.de_api;
+            de_api
+                .concurrency_limit
+                .as_ref()
+                .map(|limit| get_api_limiter(&de_api._id, de_api.version, limit))
+        });
+        let global = self.global.clone();
+
+        // the service which was polled ready is used for this request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            // the api first: a request waiting for a busy api doesn't hold a global slot
+            let mut permits = Vec::with_capacity(2);
+            for limiter in [api_limiter, global].into_iter().flatten() {
+                match limiter.acquire().await {
+                    Some(permit) => permits.push(permit),
+                    None => {
+                        SHED_COUNT.fetch_add(1, Ordering::Relaxed);
+                        return Ok(reject(StatusCode::SERVICE_UNAVAILABLE));
+                    }
+                }
+            }
+
+            // slots are released when the response body ends
+            let response = inner.call(req).await?;
+            Ok(response.map(|inner| ConcurrencyBody { inner, permits }))
+        })
+    }
+}
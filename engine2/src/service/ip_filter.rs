@@ -0,0 +1,105 @@
+use crate::config::ip_filter::IpRules;
+use crate::service::client_ip::client_ip;
+use crate::service::reject::{reject, ResponseFuture};
+use crate::service::request_id;
+use crate::service::route::Route;
+use http::{Request, Response, StatusCode};
+use log::warn;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+#[derive(Debug, Clone)]
+enum Scope {
+    /// rules of the engine, checked before the route
+    Global(Arc<IpRules>),
+    /// rules of the api found by the route
+    Api,
+}
+
+#[derive(Debug, Clone)]
+pub struct IpFilterLayer {
+    scope: Option<Scope>,
+}
+
+impl IpFilterLayer {
+    /// global rules: the denied clients can't probe which paths exist
+    pub fn global(rules: Option<IpRules>) -> Self {
+        IpFilterLayer {
+            scope: rules.map(|rules| Scope::Global(Arc::new(rules))),
+        }
+    }
+
+    /// rules of the api (after the route)
+    pub fn api() -> Self {
+        IpFilterLayer {
+            scope: Some(Scope::Api),
+        }
+    }
+}
+
+impl<S> Layer<S> for IpFilterLayer {
+    type Service = IpFilterService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IpFilterService {
+            inner,
+            scope: self.scope.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IpFilterService<S> {
+    inner: S,
+    scope: Option<Scope>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for IpFilterService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let (name, rules) = match &self.scope {
+            Some(Scope::Global(rules)) => ("global", Some(rules.as_ref())),
+            Some(Scope::Api) => match req.extensions().get::<Route>() {
+                Some(route) => (route.api.de_api.name.as_str(), route.api.ip_rules.as_ref()),
+                None => ("", None),
+            },
+            None => ("", None),
+        };
+        let rules = match rules {
+            Some(rules) => rules,
+            None => return ResponseFuture::inner(self.inner.call(req)),
+        };
+
+        let ip = match client_ip(&req) {
+            Some(ip) => ip,
+            None => return ResponseFuture::inner(self.inner.call(req)),
+        };
+
+        match rules.find_denial(&ip) {
+            Some(rule) => {
+                warn!(
+                    "[{}] ip filter: {} is denied by {} ({})",
+                    request_id::get(&req),
+                    ip,
+                    rule,
+                    name
+                );
+                ResponseFuture::reject(reject(StatusCode::FORBIDDEN))
+            }
+            None => ResponseFuture::inner(self.inner.call(req)),
+        }
+    }
+}
@@ -1,4 +1,13 @@
 pub mod access_log;
+pub mod client_ip;
+pub mod concurrency;
 pub mod cors;
+pub mod ip_filter;
 pub mod proxy;
+pub mod rate_limit;
+pub mod rate_limit_sync;
+pub mod reject;
+pub mod request_id;
 pub mod route;
+pub mod size_limit;
+pub mod trace;
@@ -1,9 +1,22 @@
-use crate::service::access_log::AccessLogRequestBody;
+use crate::monitor::{metrics, upstream};
+use crate::service::access_log::{
+    update_log_context, AccessLogRequestBody, BodyTooLarge, BodyTooLargeSignal, LogContext,
+};
+use crate::service::reject::{self, reject};
+use crate::service::request_id;
 use crate::service::route::Route;
+use crate::service::size_limit;
+use crate::service::trace;
 use crate::tls::tls_connector::make_http_or_https_client;
-use http::{Request, Response};
-use hyper::client::ResponseFuture;
+use crate::trace::Span;
+use futures_util::ready;
+use http::{Request, Response, StatusCode};
+use log::warn;
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Instant;
 use tower_service::Service;
 
 #[derive(Debug, Clone)]
@@ -12,9 +25,9 @@ pub struct ProxyService;
 impl Service<Request<AccessLogRequestBody<hyper::Body>>> for ProxyService {
     type Response = Response<hyper::Body>;
     type Error = hyper::Error;
-    type Future = ResponseFuture;
+    type Future = reject::ResponseFuture<ResponseFuture, hyper::Body>;
 
-    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         Poll::Ready(Ok(()))
     }
 
@@ -24,7 +37,120 @@ impl Service<Request<AccessLogRequestBody<hyper::Body>>> for ProxyService {
             .remove::<Route>()
             .expect("route not found.");
         let client = make_http_or_https_client();
-        *req.uri_mut() = route.target_servers[0].parse().unwrap();
-        client.request(req.map(|inner| inner.inner))
+        let target_uri = make_target_uri(&route, req.uri().query());
+        update_log_context(&req, |info| info.upstream = target_uri.clone());
+        *req.uri_mut() = match target_uri.parse() {
+            Ok(uri) => uri,
+            Err(e) => {
+                warn!("[{}] invalid target uri: {}", request_id::get(&req), e);
+                return reject::ResponseFuture::reject(reject(StatusCode::BAD_GATEWAY));
+            }
+        };
+        let client_span = trace::start_client_span(&mut req);
+
+        // body larger than max body size fails while streaming
+        let too_large = route
+            .api
+            .de_api
+            .max_body_size
+            .map(|max_body_size| req.body_mut().set_max_size(max_body_size));
+
+        reject::ResponseFuture::inner(ResponseFuture {
+            upstream: route.api.de_api.target_servers[0].clone(),
+            start: Instant::now(),
+            log_context: req.extensions().get::<LogContext>().cloned(),
+            client_span,
+            api_name: route.api.de_api.name.clone(),
+            request_id: request_id::get(&req).to_string(),
+            inner: client.request(req),
+            too_large,
+        })
+    }
+}
+
+// target server + target path (+ query of the original request)
+fn make_target_uri(route: &Route, query: Option<&str>) -> String {
+    let de_api = &route.api.de_api;
+    let mut uri = format!(
+        "{}{}",
+        de_api.target_servers[0].trim_end_matches('/'),
+        route.target_path
+    );
+    if let Some(query) = query {
+        uri.push('?');
+        uri.push_str(query);
+    }
+    uri
+}
+
+#[pin_project]
+pub struct ResponseFuture {
+    #[pin]
+    inner: hyper::client::ResponseFuture,
+    upstream: String,
+    start: Instant,
+    log_context: Option<LogContext>,
+    client_span: Option<Span>,
+    api_name: String,
+    request_id: String,
+    too_large: Option<BodyTooLargeSignal>,
+}
+
+impl Future for ResponseFuture {
+    type Output = Result<Response<hyper::Body>, hyper::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        // 413 as soon as the body is too large, the upstream request is dropped
+        if let Some(Poll::Ready(())) = this.too_large.as_ref().map(|s| s.poll_too_large(cx)) {
+            return Poll::Ready(Ok(reject_too_large(this.client_span)));
+        }
+        let result = ready!(this.inner.poll(cx));
+        if let Some(true) = this.too_large.as_ref().map(|s| s.is_too_large()) {
+            return Poll::Ready(Ok(reject_too_large(this.client_span)));
+        }
+        if let (Ok(_), Some(log_context)) = (&result, this.log_context.as_ref()) {
+            let upstream_latency = this.start.elapsed();
+            log_context.update(|info| info.upstream_latency = Some(upstream_latency));
+        }
+        if let Some(mut span) = this.client_span.take() {
+            match &result {
+                Ok(response) => {
+                    let status = response.status().as_u16();
+                    span.set_attribute("http.status_code", status as i64);
+                    if status >= 500 {
+                        span.error = Some(format!("status {}", status));
+                    }
+                }
+                Err(e) => span.error = Some(e.to_string()),
+            }
+            span.end();
+        }
+
+        match result {
+            Ok(response) => {
+                if response.status().is_server_error() {
+                    upstream::record_failure(this.upstream, response.status().to_string());
+                } else {
+                    upstream::record_success(this.upstream);
+                }
+                Poll::Ready(Ok(response))
+            }
+            Err(e) => {
+                warn!("[{}] upstream error: {}", this.request_id, e);
+                metrics::count_upstream_error(this.api_name);
+                upstream::record_failure(this.upstream, e.to_string());
+                Poll::Ready(Err(e))
+            }
+        }
+    }
+}
+
+fn reject_too_large(client_span: &mut Option<Span>) -> Response<hyper::Body> {
+    if let Some(mut span) = client_span.take() {
+        span.error = Some(BodyTooLarge.to_string());
+        span.end();
     }
+    size_limit::count_body_too_large();
+    reject(StatusCode::PAYLOAD_TOO_LARGE)
 }
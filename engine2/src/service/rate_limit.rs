@@ -0,0 +1,590 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_rate_limit.rs"]
+mod test_rate_limit;
+
+use crate::config::api::DeserializedApi;
+use crate::config::limit::{RateLimit, RateLimitAlgorithm, RateLimitKey};
+use crate::service::client_ip::client_ip;
+use crate::service::reject;
+use crate::service::route::Route;
+use dashmap::DashMap;
+use futures_util::ready;
+use http::header::{HeaderName, AUTHORIZATION, RETRY_AFTER};
+use http::{HeaderMap, HeaderValue, Request, Response, StatusCode};
+use lazy_static::lazy_static;
+use pin_project::pin_project;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::{task, time};
+use tower_layer::Layer;
+use tower_service::Service;
+
+const RATELIMIT_LIMIT: &str = "ratelimit-limit";
+const RATELIMIT_REMAINING: &str = "ratelimit-remaining";
+const RATELIMIT_RESET: &str = "ratelimit-reset";
+const API_KEY_HEADER: &str = "x-api-key";
+// subject of the requests which don't have the key (api key, jwt claim ...),
+// or have an unknown one
+const ANONYMOUS: &str = "-";
+// buckets of an api, the new subjects over it are counted as anonymous
+const MAX_BUCKETS_PER_API: usize = 10000;
+
+// counters are kept out of the api map, so they survive map swaps
+lazy_static! {
+    static ref RATE_LIMIT_BUCKETS: DashMap<BucketKey, Bucket> = DashMap::new();
+    // number of the buckets of each api id
+    static ref BUCKET_COUNTS: DashMap<String, usize> = DashMap::new();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BucketKey {
+    api_id: String,
+    api_version: usize,
+    limit_by: RateLimitKey,
+    claim: String,
+    algorithm: RateLimitAlgorithm,
+    window: u64,
+    subject: String,
+}
+
+impl BucketKey {
+    pub fn new(api_id: &str, api_version: usize, rule: &RateLimit, subject: String) -> Self {
+        BucketKey {
+            api_id: api_id.to_string(),
+            api_version,
+            limit_by: rule.limit_by,
+            claim: rule.claim.clone(),
+            algorithm: rule.algorithm,
+            window: rule.window_secs(),
+            subject,
+        }
+    }
+
+    /// key shared by the engines in the group
+    pub fn cluster_key(&self) -> String {
+        format!(
+            "{}:{}:{:?}:{}:{:?}:{}:{}",
+            self.api_id,
+            self.api_version,
+            self.limit_by,
+            self.claim,
+            self.algorithm,
+            self.window,
+            self.subject
+        )
+    }
+}
+
+/// counter exchanged with the other engines in the group
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterCounter {
+    pub key: String,
+    /// local hits since the last sync (request), hits of the whole group (response)
+    pub count: u64,
+    /// window size in seconds
+    pub window: u64,
+}
+
+/// result of a rate limit check
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u64,
+    pub remaining: u64,
+    /// seconds until the quota is fully restored
+    pub reset: u64,
+    /// seconds to wait before retrying (0 if allowed)
+    pub retry_after: u64,
+}
+
+#[derive(Debug)]
+enum BucketState {
+    Token {
+        tokens: f64,
+        updated: Instant,
+    },
+    Window {
+        start: Instant,
+        current: u64,
+        previous: u64,
+    },
+}
+
+// hits of this engine and the others for the cluster-wide limit
+#[derive(Debug, Default)]
+struct ClusterState {
+    enabled: bool,
+    pending: u64,
+    // taken to send, not in own_total until the group has them
+    sending: u64,
+    own_total: u64,
+    remote_seen: u64,
+}
+
+#[derive(Debug)]
+pub struct Bucket {
+    state: BucketState,
+    window: Duration,
+    last_seen: Instant,
+    cluster: ClusterState,
+}
+
+impl Bucket {
+    pub fn new(rule: &RateLimit, now: Instant) -> Self {
+        let state = match rule.algorithm {
+            RateLimitAlgorithm::TokenBucket => BucketState::Token {
+                tokens: rule.capacity() as f64,
+                updated: now,
+            },
+            RateLimitAlgorithm::SlidingWindow => BucketState::Window {
+                start: now,
+                current: 0,
+                previous: 0,
+            },
+        };
+        Bucket {
+            state,
+            window: Duration::from_secs(rule.window_secs()),
+            last_seen: now,
+            cluster: ClusterState {
+                enabled: rule.cluster,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// check the bucket has a request without taking it
+    pub fn peek(&mut self, rule: &RateLimit, now: Instant) -> Decision {
+        self.update(rule, now);
+        self.take(rule, now, false)
+    }
+
+    /// take one request from the bucket
+    pub fn acquire(&mut self, rule: &RateLimit, now: Instant) -> Decision {
+        self.update(rule, now);
+        let decision = self.take(rule, now, true);
+        if decision.allowed && self.cluster.enabled {
+            self.cluster.pending += 1;
+        }
+        decision
+    }
+
+    fn update(&mut self, rule: &RateLimit, now: Instant) {
+        self.last_seen = now;
+        self.window = Duration::from_secs(rule.window_secs());
+        self.cluster.enabled = rule.cluster;
+    }
+
+    // consume: false, the request is not taken even if it's allowed
+    fn take(&mut self, rule: &RateLimit, now: Instant, consume: bool) -> Decision {
+        let window = self.window.as_secs_f64();
+        match &mut self.state {
+            BucketState::Token { tokens, updated } => {
+                let capacity = rule.capacity() as f64;
+                let rate = rule.limit as f64 / window;
+                let elapsed = now.saturating_duration_since(*updated).as_secs_f64();
+                *tokens = (*tokens + elapsed * rate).min(capacity);
+                *updated = now;
+
+                let allowed = *tokens >= 1.0;
+                if allowed && consume {
+                    *tokens -= 1.0;
+                }
+                let (reset, retry_after) = if rate > 0.0 {
+                    let reset = ((capacity - *tokens) / rate).ceil() as u64;
+                    let retry_after = if allowed {
+                        0
+                    } else {
+                        ((1.0 - *tokens) / rate).ceil().max(1.0) as u64
+                    };
+                    (reset, retry_after)
+                } else {
+                    (rule.window_secs(), rule.window_secs())
+                };
+                Decision {
+                    allowed,
+                    limit: rule.capacity(),
+                    remaining: tokens.floor() as u64,
+                    reset,
+                    retry_after,
+                }
+            }
+            BucketState::Window {
+                start,
+                current,
+                previous,
+            } => {
+                // slide the window
+                let passed = now.saturating_duration_since(*start).as_secs_f64() / window;
+                if passed >= 2.0 {
+                    *previous = 0;
+                    *current = 0;
+                    *start += self.window.mul_f64(passed.floor());
+                } else if passed >= 1.0 {
+                    *previous = *current;
+                    *current = 0;
+                    *start += self.window;
+                }
+
+                let elapsed = now.saturating_duration_since(*start).as_secs_f64();
+                let weight = 1.0 - elapsed / window;
+                let estimated = *previous as f64 * weight + *current as f64;
+                let allowed = estimated + 1.0 <= rule.limit as f64;
+                if allowed && consume {
+                    *current += 1;
+                }
+
+                let used = (*previous as f64 * weight + *current as f64).ceil() as u64;
+                let reset = (window - elapsed).ceil() as u64;
+                let retry_after = if allowed {
+                    0
+                } else if *current + 1 > rule.limit || *previous == 0 {
+                    reset.max(1)
+                } else {
+                    // wait until the weight of previous window is low enough
+                    let needed = 1.0 - (rule.limit - *current - 1) as f64 / *previous as f64;
+                    ((needed * window - elapsed).ceil() as u64).max(1)
+                };
+                Decision {
+                    allowed,
+                    limit: rule.limit,
+                    remaining: rule.limit.saturating_sub(used),
+                    reset,
+                    retry_after,
+                }
+            }
+        }
+    }
+
+    /// local hits which were not sent to the group yet
+    pub fn take_pending(&mut self) -> u64 {
+        let pending = self.cluster.pending;
+        self.cluster.pending = 0;
+        self.cluster.sending += pending;
+        pending
+    }
+
+    /// the hits taken to send are in the counter of the group now
+    pub fn commit_sent(&mut self) {
+        self.cluster.own_total += self.cluster.sending;
+        self.cluster.sending = 0;
+    }
+
+    /// the hits taken to send are not in the counter of the group, send them again
+    pub fn requeue_sent(&mut self) {
+        self.cluster.pending += self.cluster.sending;
+        self.cluster.sending = 0;
+    }
+
+    /// apply the total hits of the group (after the sent hits were added to it):
+    /// the hits of the other engines use up the local quota
+    pub fn apply_total(&mut self, total: u64) {
+        self.commit_sent();
+        let cluster = &mut self.cluster;
+        if total < cluster.own_total {
+            // counter of the group has been expired
+            cluster.own_total = total;
+            cluster.remote_seen = 0;
+            return;
+        }
+
+        let remote = total - cluster.own_total;
+        let delta = remote.saturating_sub(cluster.remote_seen);
+        cluster.remote_seen = remote;
+        if delta == 0 {
+            return;
+        }
+
+        match &mut self.state {
+            BucketState::Token { tokens, .. } => {
+                *tokens = (*tokens - delta as f64).max(0.0);
+            }
+            BucketState::Window { current, .. } => {
+                *current += delta;
+            }
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_seen) > self.window * 2
+    }
+}
+
+/// remove the buckets which were not used for a while (every 60 seconds)
+pub fn handle() {
+    task::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+
+            let now = Instant::now();
+            RATE_LIMIT_BUCKETS.retain(|key, bucket| {
+                let expired = bucket.is_expired(now);
+                if expired {
+                    if let Some(mut count) = BUCKET_COUNTS.get_mut(&key.api_id) {
+                        *count = count.saturating_sub(1);
+                    }
+                }
+                !expired
+            });
+            BUCKET_COUNTS.retain(|_, count| *count > 0);
+        }
+    });
+}
+
+/// take local hits of the cluster-wide limits to send them to the group
+pub fn take_cluster_counters() -> Vec<ClusterCounter> {
+    RATE_LIMIT_BUCKETS
+        .iter_mut()
+        .filter(|entry| entry.value().cluster.enabled)
+        .map(|mut entry| ClusterCounter {
+            key: entry.key().cluster_key(),
+            window: entry.key().window,
+            count: entry.value_mut().take_pending(),
+        })
+        .collect()
+}
+
+/// the taken hits are added to the group: apply total hits of the group
+/// received from admin or the shared backend
+pub fn apply_cluster_counters(counters: Vec<ClusterCounter>) {
+    apply_totals(counters, Bucket::commit_sent);
+}
+
+/// only the hits of the totals are added to the group: apply them,
+/// the others are sent again
+pub fn apply_acknowledged_counters(counters: Vec<ClusterCounter>) {
+    apply_totals(counters, Bucket::requeue_sent);
+}
+
+// buckets without the total: `otherwise`
+fn apply_totals(counters: Vec<ClusterCounter>, otherwise: fn(&mut Bucket)) {
+    let totals: HashMap<String, u64> = counters
+        .into_iter()
+        .map(|counter| (counter.key, counter.count))
+        .collect();
+    for mut entry in RATE_LIMIT_BUCKETS.iter_mut() {
+        if !entry.value().cluster.enabled {
+            continue;
+        }
+        match totals.get(&entry.key().cluster_key()) {
+            Some(total) => entry.value_mut().apply_total(*total),
+            None => otherwise(entry.value_mut()),
+        }
+    }
+}
+
+/// the taken hits are not added to the group (failed to send), send them next time
+pub fn requeue_cluster_counters() {
+    for mut entry in RATE_LIMIT_BUCKETS.iter_mut() {
+        entry.value_mut().requeue_sent();
+    }
+}
+
+/// check all rate limits of the api, returns the most restrictive decision
+/// (the request is taken from the buckets only if all of them allow it)
+pub fn check<B>(req: &Request<B>, de_api: &DeserializedApi) -> Option<Decision> {
+    let now = Instant::now();
+    let api_id = de_api._id.as_str();
+    let api_version = de_api.version;
+    let rules = &de_api.rate_limits;
+    let keys: Vec<BucketKey> = rules
+        .iter()
+        .map(|rule| {
+            let key = get_subject(req, rule, de_api)
+                .map(|subject| BucketKey::new(api_id, api_version, rule, subject))
+                .filter(|key| {
+                    RATE_LIMIT_BUCKETS.contains_key(key)
+                        || BUCKET_COUNTS.get(api_id).map_or(0, |count| *count) < MAX_BUCKETS_PER_API
+                });
+            key.unwrap_or_else(|| BucketKey::new(api_id, api_version, rule, ANONYMOUS.to_string()))
+        })
+        .collect();
+    let decide = |consume: bool| {
+        let mut result: Option<Decision> = None;
+        for (rule, key) in rules.iter().zip(&keys) {
+            let mut bucket = RATE_LIMIT_BUCKETS.entry(key.clone()).or_insert_with(|| {
+                *BUCKET_COUNTS.entry(api_id.to_string()).or_default() += 1;
+                Bucket::new(rule, now)
+            });
+            let decision = if consume {
+                bucket.acquire(rule, now)
+            } else {
+                bucket.peek(rule, now)
+            };
+            result = match result {
+                Some(prev) if !prev.allowed => Some(prev),
+                Some(prev) if decision.allowed && prev.remaining <= decision.remaining => {
+                    Some(prev)
+                }
+                _ => Some(decision),
+            };
+        }
+        result
+    };
+
+    // 1. a denied request doesn't use up the other limits
+    match decide(false) {
+        Some(decision) if !decision.allowed => Some(decision),
+        // 2. take from all of them
+        _ => decide(true),
+    }
+}
+
+// value which the counter is keyed by (None: anonymous)
+fn get_subject<B>(req: &Request<B>, rule: &RateLimit, de_api: &DeserializedApi) -> Option<String> {
+    match rule.limit_by {
+        RateLimitKey::Api => Some(String::new()),
+        // only the keys of the api, a client can't get a new bucket by changing the key
+        RateLimitKey::ApiKey => req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| de_api.api_keys.iter().any(|key| key == value))
+            .map(|value| value.to_string()),
+        RateLimitKey::ClientIp => client_ip(req).map(|ip| ip.to_string()),
+        RateLimitKey::JwtClaim => get_jwt_claim(req.headers(), &rule.claim),
+    }
+}
+
+// part of the token decoded as a json object
+fn decode_jwt_part(part: &str) -> Option<serde_json::Value> {
+    let part = base64::decode_config(part, base64::URL_SAFE_NO_PAD).ok()?;
+    serde_json::from_slice::<serde_json::Value>(&part)
+        .ok()
+        .filter(|value| value.is_object())
+}
+
+/// claim in the payload of bearer token
+/// (the signature is not verified here: the unsigned or expired tokens are anonymous)
+pub fn get_jwt_claim(headers: &HeaderMap, claim: &str) -> Option<String> {
+    let token = headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?
+        .trim();
+    let mut parts = token.split('.');
+    let (header, payload, signature) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || signature.is_empty() {
+        return None;
+    }
+    let header = decode_jwt_part(header)?;
+    match header.get("alg").and_then(|alg| alg.as_str()) {
+        Some(alg) if !alg.eq_ignore_ascii_case("none") => {}
+        _ => return None,
+    }
+    let payload = decode_jwt_part(payload)?;
+    if let Some(exp) = payload.get("exp") {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        if exp.as_f64()? <= now {
+            return None;
+        }
+    }
+
+    match payload.get(claim)? {
+        serde_json::Value::String(value) => Some(value.clone()),
+        serde_json::Value::Null => None,
+        value => Some(value.to_string()),
+    }
+}
+
+fn insert_headers(headers: &mut HeaderMap, decision: &Decision) {
+    let values = [
+        (RATELIMIT_LIMIT, decision.limit),
+        (RATELIMIT_REMAINING, decision.remaining),
+        (RATELIMIT_RESET, decision.reset),
+    ];
+    for (name, value) in values {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    }
+    if !decision.allowed {
+        headers.insert(RETRY_AFTER, HeaderValue::from(decision.retry_after));
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer;
+
+impl RateLimitLayer {
+    pub fn new() -> Self {
+        RateLimitLayer
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RateLimitService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let decision = match req.extensions().get::<Route>() {
+            Some(route) if !route.api.de_api.rate_limits.is_empty() => {
+                check(&req, &route.api.de_api)
+            }
+            _ => None,
+        };
+
+        let inner = match &decision {
+            Some(decision) if !decision.allowed => {
+                reject::ResponseFuture::reject(reject::reject(StatusCode::TOO_MANY_REQUESTS))
+            }
+            _ => reject::ResponseFuture::inner(self.inner.call(req)),
+        };
+        ResponseFuture { inner, decision }
+    }
+}
+
+/// future of the request (or the rejected one) with the rate limit headers
+#[pin_project]
+pub struct ResponseFuture<F, B> {
+    #[pin]
+    inner: reject::ResponseFuture<F, B>,
+    decision: Option<Decision>,
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut response = ready!(this.inner.poll(cx)?);
+        if let Some(decision) = this.decision {
+            insert_headers(response.headers_mut(), decision);
+        }
+        Poll::Ready(Ok(response))
+    }
+}
@@ -0,0 +1,177 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_rate_limit_sync.rs"]
+mod test_rate_limit_sync;
+
+use crate::service::rate_limit::{self, ClusterCounter};
+use log::warn;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::{task, time};
+
+// counters are exchanged every second, so the group can overshoot the limit
+// by the hits of one second at most
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+// connect and each round trip to redis (a hung redis doesn't stall the sync)
+const REDIS_TIMEOUT: Duration = Duration::from_secs(2);
+
+// if false, counters are exchanged through the admin poll channel
+static USE_REDIS: AtomicBool = AtomicBool::new(false);
+
+/// start to share the counters of cluster-wide rate limits in the group
+pub fn handle(redis_address: Option<String>, group_name: String) {
+    let address = match redis_address {
+        Some(address) => address,
+        None => return,
+    };
+    USE_REDIS.store(true, Ordering::SeqCst);
+
+    task::spawn(async move {
+        let mut interval = time::interval(SYNC_INTERVAL);
+        let mut client: Option<RedisClient> = None;
+        loop {
+            interval.tick().await;
+
+            let counters = rate_limit::take_cluster_counters();
+            if counters.is_empty() {
+                continue;
+            }
+
+            if client.is_none() {
+                match RedisClient::connect(&address).await {
+                    Ok(connected) => client = Some(connected),
+                    Err(e) => {
+                        warn!("rate limit: failed to connect to {}: {}", address, e);
+                        rate_limit::requeue_cluster_counters();
+                        continue;
+                    }
+                }
+            }
+
+            let redis = client.as_mut().unwrap();
+            match redis.increase(&group_name, &counters).await {
+                Ok(totals) => rate_limit::apply_cluster_counters(totals),
+                Err((totals, e)) => {
+                    // local hits not acknowledged are sent again with the next sync
+                    warn!("rate limit: failed to sync counters: {}", e);
+                    rate_limit::apply_acknowledged_counters(totals);
+                    client = None;
+                }
+            }
+        }
+    });
+}
+
+/// counters to send to admin with the poll message (empty if redis is used)
+pub fn take_counters_for_admin() -> Vec<ClusterCounter> {
+    if USE_REDIS.load(Ordering::SeqCst) {
+        return vec![];
+    }
+    rate_limit::take_cluster_counters()
+}
+
+/// admin received the counters: apply the totals of the group (ignored if redis is used)
+pub fn apply_counters_from_admin(totals: Vec<ClusterCounter>) {
+    if USE_REDIS.load(Ordering::SeqCst) {
+        return;
+    }
+    rate_limit::apply_cluster_counters(totals)
+}
+
+/// admin didn't receive the counters: send them with the next poll
+pub fn requeue_counters_for_admin() {
+    if USE_REDIS.load(Ordering::SeqCst) {
+        return;
+    }
+    rate_limit::requeue_cluster_counters()
+}
+
+/// minimal client of redis protocol (RESP) for INCRBY/EXPIRE
+pub struct RedisClient {
+    stream: BufReader<TcpStream>,
+}
+
+impl RedisClient {
+    pub async fn connect(address: &str) -> Result<Self, String> {
+        let stream = time::timeout(REDIS_TIMEOUT, TcpStream::connect(address))
+            .await
+            .map_err(|_| String::from("timed out"))?
+            .map_err(|e| e.to_string())?;
+        Ok(RedisClient {
+            stream: BufReader::new(stream),
+        })
+    }
+
+    /// add local hits to the counters of the group and get the totals
+    /// (Err: the totals of the counters acknowledged before the error)
+    pub async fn increase(
+        &mut self,
+        group_name: &str,
+        counters: &[ClusterCounter],
+    ) -> Result<Vec<ClusterCounter>, (Vec<ClusterCounter>, String)> {
+        // pipelining: INCRBY and EXPIRE for each counter
+        let mut request = Vec::new();
+        for counter in counters {
+            let key = format!("osori:ratelimit:{}:{}", group_name, counter.key);
+            let count = counter.count.to_string();
+            let expire = (counter.window * 2).to_string();
+            write_command(&mut request, &["INCRBY", &key, &count]);
+            write_command(&mut request, &["EXPIRE", &key, &expire]);
+        }
+        match time::timeout(REDIS_TIMEOUT, self.stream.get_mut().write_all(&request)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err((vec![], e.to_string())),
+            Err(_) => return Err((vec![], String::from("timed out"))),
+        }
+
+        let mut totals = Vec::with_capacity(counters.len());
+        let mut error = None;
+        for counter in counters {
+            match self.read_integer().await {
+                Ok(Ok(total)) => totals.push(ClusterCounter {
+                    key: counter.key.clone(),
+                    count: total.max(0) as u64,
+                    window: counter.window,
+                }),
+                // not added, the next replies are read
+                Ok(Err(e)) => error = Some(e),
+                Err(e) => return Err((totals, e)),
+            }
+            if let Err(e) = self.read_integer().await {
+                return Err((totals, e));
+            }
+        }
+        match error {
+            Some(e) => Err((totals, e)),
+            None => Ok(totals),
+        }
+    }
+
+    // Err: the connection is broken, Ok(Err): error reply of the command
+    async fn read_integer(&mut self) -> Result<Result<i64, String>, String> {
+        let mut line = String::new();
+        let read = time::timeout(REDIS_TIMEOUT, self.stream.read_line(&mut line))
+            .await
+            .map_err(|_| String::from("timed out"))?
+            .map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Err(String::from("connection closed"));
+        }
+
+        let line = line.trim_end();
+        match line.split_at(line.len().min(1)) {
+            (":", value) => value.parse::<i64>().map(Ok).map_err(|e| e.to_string()),
+            ("-", message) => Ok(Err(message.to_string())),
+            _ => Err(format!("unexpected reply: {}", line)),
+        }
+    }
+}
+
+fn write_command(buf: &mut Vec<u8>, args: &[&str]) {
+    buf.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        buf.extend_from_slice(format!("${}\r\n{}\r\n", arg.len(), arg).as_bytes());
+    }
+}
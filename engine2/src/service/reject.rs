@@ -0,0 +1,52 @@
+use http::{Response, StatusCode};
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// make an empty response for the request rejected by the engine
+pub fn reject<B: Default>(status: StatusCode) -> Response<B> {
+    let mut response = Response::new(B::default());
+    *response.status_mut() = status;
+    response
+}
+
+/// future of the layer which can reject the request without calling inner service
+#[pin_project(project = ResponseFutureProj)]
+pub enum ResponseFuture<F, B> {
+    Inner {
+        #[pin]
+        inner: F,
+    },
+    Reject {
+        response: Option<Response<B>>,
+    },
+}
+
+impl<F, B> ResponseFuture<F, B> {
+    pub fn inner(inner: F) -> Self {
+        ResponseFuture::Inner { inner }
+    }
+
+    pub fn reject(response: Response<B>) -> Self {
+        ResponseFuture::Reject {
+            response: Some(response),
+        }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Inner { inner } => inner.poll(cx),
+            ResponseFutureProj::Reject { response } => {
+                Poll::Ready(Ok(response.take().expect("polled after ready")))
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_request_id.rs"]
+mod test_request_id;
+
+use http::{HeaderValue, Request};
+use uuid::Uuid;
+
+pub const X_REQUEST_ID: &str = "x-request-id";
+
+// longer ids from the client are replaced
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// id of the request, shared with the inner layers through request extensions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// reuse the valid X-Request-Id of the client or make a new one,
+/// the header is forwarded to the upstream
+pub fn assign<B>(req: &mut Request<B>) -> RequestId {
+    let id = req
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    // always valid header value
+    req.headers_mut()
+        .insert(X_REQUEST_ID, HeaderValue::from_str(&id).unwrap());
+    let id = RequestId(id);
+    req.extensions_mut().insert(id.clone());
+    id
+}
+
+/// id of the request for system log ("-" if it's not assigned)
+pub fn get<B>(req: &Request<B>) -> &str {
+    req.extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.as_str())
+        .unwrap_or("-")
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
@@ -1,27 +1,30 @@
-use http::{Request, Response};
+use crate::config::api::{self, ApiMatch, ManagedApi};
+use crate::config::matcher::RouteRequest;
+use crate::service::access_log::update_log_context;
+use crate::service::reject::{reject, ResponseFuture};
+use crate::service::request_id;
+use http::{Request, Response, StatusCode};
+use log::debug;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use tower_layer::Layer;
 use tower_service::Service;
 
+/// api found by the request line (inserted into request extensions)
 #[derive(Debug, Clone)]
 pub struct Route {
-    pub target_servers: Vec<String>,
-}
-
-pub fn dummy_route() -> Route {
-    Route {
-        target_servers: vec!["https://httpbin.org".into()],
-    }
+    /// shared with the map, never changed
+    pub api: Arc<ManagedApi>,
+    /// path of the request to the upstream
+    pub target_path: String,
 }
 
 #[derive(Debug, Clone)]
-pub struct RouteLayer {
-    route: Route,
-}
+pub struct RouteLayer;
 
 impl RouteLayer {
-    pub fn new(route: Route) -> Self {
-        RouteLayer { route }
+    pub fn new() -> Self {
+        RouteLayer
     }
 }
 
@@ -29,34 +32,45 @@ impl<S> Layer<S> for RouteLayer {
     type Service = RouteService<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        RouteService {
-            inner,
-            route: self.route.clone(),
-        }
+        RouteService { inner }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct RouteService<S> {
     inner: S,
-    route: Route,
 }
 
 impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RouteService<S>
 where
     S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
 {
     type Response = S::Response;
     type Error = S::Error;
-    type Future = S::Future;
+    type Future = ResponseFuture<S::Future, ResBody>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.inner.poll_ready(cx)
     }
 
     fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
-        req.extensions_mut().insert(self.route.clone());
-        println!("Route complete: {}", self.route.target_servers[0]);
-        self.inner.call(req)
+        let found = api::find_api_by_request(&RouteRequest::from_request(&req));
+        match found {
+            Some(ApiMatch { api, target_path }) => {
+                debug!(
+                    "[{}] Route complete: {}",
+                    request_id::get(&req),
+                    api.de_api.name
+                );
+                update_log_context(&req, |info| {
+                    info.api_name = api.de_api.name.clone();
+                    info.api_version = api.de_api.version;
+                });
+                req.extensions_mut().insert(Route { api, target_path });
+                ResponseFuture::inner(self.inner.call(req))
+            }
+            None => ResponseFuture::reject(reject(StatusCode::NOT_FOUND)),
+        }
     }
 }
@@ -0,0 +1,154 @@
+use crate::config::limit::SizeLimit;
+use crate::service::reject::{reject, ResponseFuture};
+use crate::service::route::Route;
+use http::header::CONTENT_LENGTH;
+use http::{Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+// rejected requests since the last poll
+static BODY_TOO_LARGE: AtomicUsize = AtomicUsize::new(0);
+static URI_TOO_LONG: AtomicUsize = AtomicUsize::new(0);
+static HEADER_TOO_LARGE: AtomicUsize = AtomicUsize::new(0);
+
+/// requests rejected by the size limits (for the poll message)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SizeRejections {
+    pub body_too_large: usize,
+    pub uri_too_long: usize,
+    pub header_too_large: usize,
+}
+
+pub fn take_rejections() -> SizeRejections {
+    SizeRejections {
+        body_too_large: BODY_TOO_LARGE.swap(0, Ordering::Relaxed),
+        uri_too_long: URI_TOO_LONG.swap(0, Ordering::Relaxed),
+        header_too_large: HEADER_TOO_LARGE.swap(0, Ordering::Relaxed),
+    }
+}
+
+/// the rejections were not reported, report them next time
+pub fn requeue_rejections(rejections: &SizeRejections) {
+    BODY_TOO_LARGE.fetch_add(rejections.body_too_large, Ordering::Relaxed);
+    URI_TOO_LONG.fetch_add(rejections.uri_too_long, Ordering::Relaxed);
+    HEADER_TOO_LARGE.fetch_add(rejections.header_too_large, Ordering::Relaxed);
+}
+
+/// count the request whose body exceeded max body size while streaming
+pub fn count_body_too_large() {
+    BODY_TOO_LARGE.fetch_add(1, Ordering::Relaxed);
+}
+
+/// status code to reject the request with, or None if it's in the global limits
+pub fn check<B>(req: &Request<B>, limit: &SizeLimit) -> Option<StatusCode> {
+    if let Some(max) = limit.max_uri_length {
+        let length = req
+            .uri()
+            .path_and_query()
+            .map(|path| path.as_str().len())
+            .unwrap_or(0);
+        if length > max {
+            URI_TOO_LONG.fetch_add(1, Ordering::Relaxed);
+            return Some(StatusCode::URI_TOO_LONG);
+        }
+    }
+
+    let headers = req.headers();
+    let too_many = matches!(limit.max_header_count, Some(max) if headers.len() > max);
+    let too_large = matches!(limit.max_header_size, Some(max) if headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum::<usize>()
+        > max);
+    if too_many || too_large {
+        HEADER_TOO_LARGE.fetch_add(1, Ordering::Relaxed);
+        return Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    }
+
+    None
+}
+
+/// declared body size over the max body size of the api (chunked body is checked while streaming)
+pub fn check_body<B>(req: &Request<B>) -> Option<StatusCode> {
+    let max = req
+        .extensions()
+        .get::<Route>()
+        .and_then(|route| route.api.de_api.max_body_size)?;
+    let length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if matches!(length, Some(length) if length > max) {
+        count_body_too_large();
+        return Some(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    None
+}
+
+#[derive(Debug, Clone)]
+pub struct SizeLimitLayer {
+    /// None: the body limit of the api
+    global: Option<Arc<SizeLimit>>,
+}
+
+impl SizeLimitLayer {
+    /// uri and header limits of the engine, checked before the route
+    pub fn global(limit: SizeLimit) -> Self {
+        SizeLimitLayer {
+            global: Some(Arc::new(limit)),
+        }
+    }
+
+    /// max body size of the api (after the route)
+    pub fn api() -> Self {
+        SizeLimitLayer { global: None }
+    }
+}
+
+impl<S> Layer<S> for SizeLimitLayer {
+    type Service = SizeLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SizeLimitService {
+            inner,
+            global: self.global.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SizeLimitService<S> {
+    inner: S,
+    global: Option<Arc<SizeLimit>>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SizeLimitService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let status = match &self.global {
+            Some(limit) => check(&req, limit),
+            None => check_body(&req),
+        };
+        match status {
+            Some(status) => ResponseFuture::reject(reject(status)),
+            None => ResponseFuture::inner(self.inner.call(req)),
+        }
+    }
+}
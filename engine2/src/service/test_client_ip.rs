@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod test_client_ip {
+    use super::super::*;
+    use http::HeaderValue;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn trusted() -> Vec<Cidr> {
+        vec!["10.0.0.0/8".parse().unwrap(), "::1".parse().unwrap()]
+    }
+
+    #[test]
+    fn test_untrusted_remote() {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("1.1.1.1"));
+        let client = resolve_client_ip(
+            ip("192.168.0.1"),
+            &headers,
+            &trusted(),
+            ForwardedHeader::XForwardedFor,
+        );
+        assert_eq!(client, ip("192.168.0.1"));
+    }
+
+    #[test]
+    fn test_x_forwarded_for() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            X_FORWARDED_FOR,
+            HeaderValue::from_static("6.6.6.6, 1.1.1.1, 10.0.0.2"),
+        );
+        let client = resolve_client_ip(
+            ip("10.0.0.1"),
+            &headers,
+            &trusted(),
+            ForwardedHeader::XForwardedFor,
+        );
+        assert_eq!(client, ip("1.1.1.1"));
+
+        // all hops are trusted
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("10.0.0.3"));
+        let client = resolve_client_ip(
+            ip("10.0.0.1"),
+            &headers,
+            &trusted(),
+            ForwardedHeader::XForwardedFor,
+        );
+        assert_eq!(client, ip("10.0.0.3"));
+    }
+
+    #[test]
+    fn test_forwarded() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            FORWARDED,
+            HeaderValue::from_static(
+                r#"for=1.1.1.1;proto=http, for="[2001:db8:cafe::17]:4711", for=10.0.0.2:80"#,
+            ),
+        );
+        let client = resolve_client_ip(ip("::1"), &headers, &trusted(), ForwardedHeader::Forwarded);
+        assert_eq!(client, ip("2001:db8:cafe::17"));
+
+        headers.insert(FORWARDED, HeaderValue::from_static("for=unknown"));
+        let client = resolve_client_ip(ip("::1"), &headers, &trusted(), ForwardedHeader::Forwarded);
+        assert_eq!(client, ip("::1"));
+    }
+
+    #[test]
+    fn test_spoofed_forwarded() {
+        // the proxy appends to X-Forwarded-For, Forwarded is sent by the client
+        let mut headers = HeaderMap::new();
+        headers.insert(FORWARDED, HeaderValue::from_static("for=10.0.0.9"));
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("1.1.1.1"));
+        let header = ForwardedHeader::XForwardedFor;
+        let client = resolve_client_ip(ip("10.0.0.1"), &headers, &trusted(), header);
+        assert_eq!(client, ip("1.1.1.1"));
+
+        headers.remove(X_FORWARDED_FOR);
+        let client = resolve_client_ip(ip("10.0.0.1"), &headers, &trusted(), header);
+        assert_eq!(client, ip("10.0.0.1"));
+    }
+}
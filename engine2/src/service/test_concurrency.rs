@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod test_concurrency {
+    use super::super::*;
+
+    #[tokio::test]
+    async fn test_limiter_sheds_when_queue_is_full() {
+        let limiter = Arc::new(Limiter::new(ConcurrencyLimit {
+            max_concurrent: 1,
+            max_queue: 1,
+            queue_timeout: 0,
+        }));
+
+        let permit = limiter.acquire().await.unwrap();
+
+        // second request waits in the queue
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire().await.is_some() }
+        });
+        tokio::task::yield_now().await;
+        while limiter.queued.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // third request is shed
+        assert!(limiter.acquire().await.is_none());
+
+        drop(permit);
+        assert!(waiting.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_limiter_queue_timeout() {
+        let limiter = Limiter::new(ConcurrencyLimit {
+            max_concurrent: 1,
+            max_queue: 1,
+            queue_timeout: 1,
+        });
+
+        let _permit = limiter.acquire().await.unwrap();
+        assert!(limiter.acquire().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_body_holds_permit() {
+        let limiter = Limiter::new(ConcurrencyLimit {
+            max_concurrent: 1,
+            max_queue: 0,
+            queue_timeout: 0,
+        });
+
+        let permit = limiter.acquire().await.unwrap();
+        let mut body = ConcurrencyBody {
+            inner: hyper::Body::from("response"),
+            permits: vec![permit],
+        };
+        assert!(body.data().await.is_some());
+        assert_eq!(limiter.semaphore.available_permits(), 0);
+        // end of the body
+        assert!(body.data().await.is_none());
+        assert_eq!(limiter.semaphore.available_permits(), 1);
+    }
+}
@@ -0,0 +1,196 @@
+#[cfg(test)]
+mod test_rate_limit {
+    use super::super::*;
+    use crate::config::test_fixture::make_api;
+    use http::Request;
+    use std::time::{Duration, Instant};
+
+    fn make_rule(algorithm: RateLimitAlgorithm, limit: u64, window: u64) -> RateLimit {
+        RateLimit {
+            limit_by: RateLimitKey::Api,
+            claim: String::new(),
+            algorithm,
+            limit,
+            window,
+            burst: 0,
+            cluster: false,
+        }
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let rule = make_rule(RateLimitAlgorithm::TokenBucket, 2, 10);
+        let now = Instant::now();
+        let mut bucket = Bucket::new(&rule, now);
+
+        assert!(bucket.acquire(&rule, now).allowed);
+        let decision = bucket.acquire(&rule, now);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 0);
+
+        let decision = bucket.acquire(&rule, now);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, 5);
+
+        // refilled one token after 5 seconds
+        let later = now + Duration::from_secs(5);
+        assert!(bucket.acquire(&rule, later).allowed);
+        assert!(!bucket.acquire(&rule, later).allowed);
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let rule = make_rule(RateLimitAlgorithm::SlidingWindow, 4, 10);
+        let now = Instant::now();
+        let mut bucket = Bucket::new(&rule, now);
+
+        for _ in 0..4 {
+            assert!(bucket.acquire(&rule, now).allowed);
+        }
+        let decision = bucket.acquire(&rule, now);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, 10);
+
+        // half of the previous window is still counted
+        let later = now + Duration::from_secs(15);
+        assert!(bucket.acquire(&rule, later).allowed);
+        assert!(bucket.acquire(&rule, later).allowed);
+        assert!(!bucket.acquire(&rule, later).allowed);
+
+        // previous window is expired
+        let later = now + Duration::from_secs(30);
+        assert_eq!(bucket.acquire(&rule, later).remaining, 3);
+    }
+
+    #[test]
+    fn test_check_rejected_by_one_rule() {
+        let mut api = make_api("test-check-rejected", 1, "/a", &["GET"]);
+        api.rate_limits = vec![
+            make_rule(RateLimitAlgorithm::SlidingWindow, 1, 10),
+            make_rule(RateLimitAlgorithm::TokenBucket, 10, 10),
+        ];
+        let rules = &api.rate_limits;
+        let api_id = api._id.as_str();
+        let req = Request::builder().body(()).unwrap();
+        assert!(check(&req, &api).unwrap().allowed);
+        for _ in 0..3 {
+            assert!(!check(&req, &api).unwrap().allowed);
+        }
+
+        // the rejected requests didn't take the tokens of the second rule
+        let key = BucketKey::new(api_id, 1, &rules[1], String::new());
+        let mut bucket = RATE_LIMIT_BUCKETS.get_mut(&key).unwrap();
+        assert_eq!(bucket.peek(&rules[1], Instant::now()).remaining, 9);
+    }
+
+    #[test]
+    fn test_cluster_counter() {
+        let mut rule = make_rule(RateLimitAlgorithm::SlidingWindow, 4, 10);
+        rule.cluster = true;
+        let now = Instant::now();
+        let mut bucket = Bucket::new(&rule, now);
+
+        assert!(bucket.acquire(&rule, now).allowed);
+        assert_eq!(bucket.take_pending(), 1);
+        assert_eq!(bucket.take_pending(), 0);
+
+        // 2 hits of the other engines
+        bucket.apply_total(3);
+        assert_eq!(bucket.acquire(&rule, now).remaining, 0);
+        assert!(!bucket.acquire(&rule, now).allowed);
+
+        // same total is applied only once
+        bucket.apply_total(3);
+        assert_eq!(bucket.take_pending(), 1);
+
+        // failed to send: the hit is sent again and not in the total of the group
+        let mut bucket = Bucket::new(&rule, now);
+        assert!(bucket.acquire(&rule, now).allowed);
+        assert_eq!(bucket.take_pending(), 1);
+        bucket.requeue_sent();
+        // 2 hits of the other engines
+        bucket.apply_total(2);
+        assert_eq!(bucket.acquire(&rule, now).remaining, 0);
+        assert_eq!(bucket.take_pending(), 2);
+    }
+
+    fn bearer(header: &str, payload: &str) -> String {
+        let encode = |part: &str| base64::encode_config(part, base64::URL_SAFE_NO_PAD);
+        format!("Bearer {}.{}.signature", encode(header), encode(payload))
+    }
+
+    #[test]
+    fn test_get_jwt_claim() {
+        let header = r#"{"alg":"HS256","typ":"JWT"}"#;
+        let req = Request::builder()
+            .header(
+                "authorization",
+                bearer(header, r#"{"sub":"osori","tier":3}"#),
+            )
+            .body(())
+            .unwrap();
+
+        assert_eq!(
+            get_jwt_claim(req.headers(), "sub"),
+            Some("osori".to_string())
+        );
+        assert_eq!(get_jwt_claim(req.headers(), "tier"), Some("3".to_string()));
+        assert_eq!(get_jwt_claim(req.headers(), "none"), None);
+
+        // unsigned or expired
+        let mut headers = HeaderMap::new();
+        let token = bearer(r#"{"alg":"none"}"#, r#"{"sub":"osori"}"#);
+        headers.insert(AUTHORIZATION, token.parse().unwrap());
+        assert_eq!(get_jwt_claim(&headers, "sub"), None);
+        let token = bearer(header, r#"{"sub":"osori","exp":1000}"#);
+        headers.insert(AUTHORIZATION, token.parse().unwrap());
+        assert_eq!(get_jwt_claim(&headers, "sub"), None);
+    }
+
+    #[test]
+    fn test_unknown_subject() {
+        let mut api = make_api("test-unknown-subject", 1, "/a", &["GET"]);
+        api.api_keys = vec![String::from("key-1")];
+        let mut rule = make_rule(RateLimitAlgorithm::SlidingWindow, 1, 10);
+        rule.limit_by = RateLimitKey::ApiKey;
+        api.rate_limits = vec![rule];
+
+        let check_key = |api: &DeserializedApi, key: &str| {
+            let req = Request::builder()
+                .header(API_KEY_HEADER, key)
+                .body(())
+                .unwrap();
+            check(&req, api).unwrap().allowed
+        };
+        assert!(check_key(&api, "key-1"));
+        assert!(!check_key(&api, "key-1"));
+        // the unknown keys share the anonymous bucket
+        assert!(check_key(&api, "random-1"));
+        assert!(!check_key(&api, "random-2"));
+
+        // other versions of the api have their own buckets
+        let mut api2 = api.clone();
+        api2.version = 2;
+        assert!(check_key(&api2, "key-1"));
+
+        // the new subjects over the limit of the api are anonymous
+        let mut rule = make_rule(RateLimitAlgorithm::SlidingWindow, 1, 10);
+        rule.limit_by = RateLimitKey::JwtClaim;
+        rule.claim = String::from("sub");
+        api._id = String::from("test-max-buckets");
+        api.rate_limits = vec![rule];
+        let check_sub = |sub: usize| {
+            let payload = format!(r#"{{"sub":"{}"}}"#, sub);
+            let req = Request::builder()
+                .header("authorization", bearer(r#"{"alg":"HS256"}"#, &payload))
+                .body(())
+                .unwrap();
+            check(&req, &api).unwrap().allowed
+        };
+        for sub in 0..MAX_BUCKETS_PER_API {
+            assert!(check_sub(sub));
+        }
+        assert!(check_sub(MAX_BUCKETS_PER_API));
+        assert!(!check_sub(MAX_BUCKETS_PER_API + 1));
+    }
+}
@@ -0,0 +1,115 @@
+#[cfg(test)]
+mod test_rate_limit_sync {
+    use super::super::*;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // stand-in of redis server which understands INCRBY and EXPIRE
+    async fn spawn_redis() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let mut counters: HashMap<String, i64> = HashMap::new();
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    return;
+                }
+                let argc: usize = line.trim_end()[1..].parse().unwrap();
+                let mut args = Vec::new();
+                for _ in 0..argc * 2 {
+                    let mut arg = String::new();
+                    stream.read_line(&mut arg).await.unwrap();
+                    args.push(arg.trim_end().to_string());
+                }
+                // skip the lengths
+                let args: Vec<String> = args.into_iter().skip(1).step_by(2).collect();
+
+                let reply = match args[0].as_str() {
+                    "INCRBY" if args[1].ends_with(":bad") => {
+                        String::from("-ERR not an integer\r\n")
+                    }
+                    "INCRBY" => {
+                        let counter = counters.entry(args[1].clone()).or_insert(0);
+                        *counter += args[2].parse::<i64>().unwrap();
+                        format!(":{}\r\n", counter)
+                    }
+                    "EXPIRE" => String::from(":1\r\n"),
+                    _ => String::from("-ERR unknown command\r\n"),
+                };
+                stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        address
+    }
+
+    fn make_counter(key: &str, count: u64) -> ClusterCounter {
+        ClusterCounter {
+            key: key.to_string(),
+            count,
+            window: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redis_increase() {
+        let address = spawn_redis().await;
+        let mut client = RedisClient::connect(&address).await.unwrap();
+
+        let totals = client
+            .increase("group", &[make_counter("a", 3), make_counter("b", 0)])
+            .await
+            .unwrap();
+        assert_eq!(totals, vec![make_counter("a", 3), make_counter("b", 0)]);
+
+        let totals = client
+            .increase("group", &[make_counter("a", 2)])
+            .await
+            .unwrap();
+        assert_eq!(totals, vec![make_counter("a", 5)]);
+
+        // only the acknowledged counters have the totals
+        let (totals, _) = client
+            .increase(
+                "group",
+                &[
+                    make_counter("a", 1),
+                    make_counter("bad", 1),
+                    make_counter("b", 1),
+                ],
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(totals, vec![make_counter("a", 6), make_counter("b", 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_redis_timeout() {
+        // accepts, never replies
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let _stream = listener.accept().await.unwrap();
+            time::sleep(Duration::from_secs(60)).await;
+        });
+
+        let mut client = RedisClient::connect(&address).await.unwrap();
+        let result = client.increase("group", &[make_counter("a", 1)]).await;
+        assert_eq!(result, Err((vec![], String::from("timed out"))));
+    }
+
+    #[test]
+    fn test_write_command() {
+        let mut buf = Vec::new();
+        write_command(&mut buf, &["INCRBY", "key", "10"]);
+        assert_eq!(
+            buf,
+            b"*3\r\n$6\r\nINCRBY\r\n$3\r\nkey\r\n$2\r\n10\r\n".to_vec()
+        );
+    }
+}
@@ -0,0 +1,35 @@
+#[cfg(test)]
+mod test_request_id {
+    use super::super::*;
+
+    fn make_request(id: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder().uri("/v1/test");
+        if let Some(id) = id {
+            builder = builder.header(X_REQUEST_ID, id);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn test_reuse_request_id() {
+        let mut req = make_request(Some("client-id_1.2:3"));
+        let id = assign(&mut req);
+        assert_eq!(id.0, "client-id_1.2:3");
+        assert_eq!(get(&req), "client-id_1.2:3");
+    }
+
+    #[test]
+    fn test_make_request_id() {
+        let too_long = "a".repeat(MAX_REQUEST_ID_LENGTH + 1);
+        for invalid in [None, Some(""), Some("has space"), Some(too_long.as_str())] {
+            let mut req = make_request(invalid);
+            let id = assign(&mut req);
+            assert_eq!(id.0.len(), 36);
+            assert_eq!(req.headers()[X_REQUEST_ID], id.0.as_str());
+        }
+
+        let mut first = make_request(None);
+        let mut second = make_request(None);
+        assert_ne!(assign(&mut first), assign(&mut second));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::service::access_log::LogContext;
+use crate::service::request_id;
+use crate::trace::context::{self, Propagation, SpanId, TraceId};
+use crate::trace::{self, Span, SpanContext, SpanKind};
+use futures_util::ready;
+use http::{Request, Response};
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// server span of the request (tracing must be enabled)
+#[derive(Debug, Clone)]
+pub struct TraceLayer;
+
+impl<S> Layer<S> for TraceLayer {
+    type Service = TraceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceService { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for TraceService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: std::fmt::Display,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        if !trace::is_enabled() {
+            return ResponseFuture {
+                inner: self.inner.call(req),
+                span: None,
+            };
+        }
+
+        let parent = context::extract(req.headers());
+        let trace_id = parent
+            .as_ref()
+            .map(|parent| parent.trace_id)
+            .unwrap_or_else(TraceId::random);
+        let span_context = SpanContext {
+            trace_id,
+            span_id: SpanId::random(),
+            sampled: trace::should_sample(parent.as_ref(), trace_id, trace::get_sample_ratio()),
+            trace_state: parent
+                .as_ref()
+                .map(|parent| parent.trace_state.clone())
+                .unwrap_or_default(),
+            propagation: parent
+                .as_ref()
+                .map(|parent| parent.propagation)
+                .unwrap_or(Propagation::W3c),
+        };
+
+        // not sampled spans are only propagated
+        let span = if span_context.sampled {
+            let mut span = Span::start(
+                format!("{} {}", req.method(), req.uri().path()),
+                SpanKind::Server,
+                &span_context,
+            );
+            span.parent_span_id = parent.map(|parent| parent.span_id);
+            span.set_attribute("http.method", req.method().as_str());
+            span.set_attribute("http.target", req.uri().to_string());
+            span.set_attribute("http.request_id", request_id::get(&req));
+            Some(ServerSpan {
+                span,
+                log_context: req.extensions().get::<LogContext>().cloned(),
+            })
+        } else {
+            None
+        };
+        req.extensions_mut().insert(span_context);
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            span,
+        }
+    }
+}
+
+struct ServerSpan {
+    span: Span,
+    // api is filled by the route layer
+    log_context: Option<LogContext>,
+}
+
+impl ServerSpan {
+    fn end(mut self, status: Option<u16>, error: Option<String>) {
+        if let Some(log_context) = &self.log_context {
+            let (api_name, api_version) = log_context.api();
+            if !api_name.is_empty() {
+                self.span.set_attribute("osori.api.name", api_name);
+                self.span
+                    .set_attribute("osori.api.version", api_version as i64);
+            }
+        }
+        if let Some(status) = status {
+            self.span.set_attribute("http.status_code", status as i64);
+            if status >= 500 {
+                self.span.error = Some(format!("status {}", status));
+            }
+        }
+        if error.is_some() {
+            self.span.error = error;
+        }
+        self.span.end();
+    }
+}
+
+#[pin_project]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: F,
+    span: Option<ServerSpan>,
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    E: std::fmt::Display,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(this.inner.poll(cx));
+        if let Some(span) = this.span.take() {
+            match &result {
+                Ok(response) => span.end(Some(response.status().as_u16()), None),
+                Err(e) => span.end(None, Some(e.to_string())),
+            }
+        }
+        Poll::Ready(result)
+    }
+}
+
+/// client span of the upstream request, the context is injected into the request
+pub fn start_client_span<B>(req: &mut Request<B>) -> Option<Span> {
+    let parent = req.extensions().get::<SpanContext>()?.clone();
+    let client_context = SpanContext {
+        span_id: SpanId::random(),
+        ..parent.clone()
+    };
+    context::inject(
+        req.headers_mut(),
+        client_context.trace_id,
+        client_context.span_id,
+        client_context.sampled,
+        &client_context.trace_state,
+        client_context.propagation,
+    );
+    if !client_context.sampled {
+        return None;
+    }
+
+    let mut span = Span::start(
+        format!("{} {}", req.method(), req.uri().path()),
+        SpanKind::Client,
+        &client_context,
+    );
+    span.parent_span_id = Some(parent.span_id);
+    span.set_attribute("http.method", req.method().as_str());
+    span.set_attribute("http.url", req.uri().to_string());
+    Some(span)
+}
@@ -1,3 +1,4 @@
+use crate::monitor::metrics;
 use core::task::{Context, Poll};
 use futures_util::ready;
 use hyper::server::accept::Accept;
@@ -10,10 +11,10 @@ use std::{fs, io, sync};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio_rustls::rustls::ServerConfig;
 
-/// --------------- hyper-rustls/examples/server.rs --------------- ///
+// --------------- hyper-rustls/examples/server.rs --------------- //
 
 pub fn error(err: String) -> io::Error {
-    io::Error::new(io::ErrorKind::Other, err)
+    io::Error::other(err)
 }
 
 pub fn make_tls_config() -> Arc<ServerConfig> {
@@ -68,7 +69,10 @@ impl AsyncRead for TlsStream {
                     pin.state = State::Streaming(stream);
                     result
                 }
-                Err(err) => Poll::Ready(Err(err)),
+                Err(err) => {
+                    metrics::count_tls_handshake_failure();
+                    Poll::Ready(Err(err))
+                }
             },
             State::Streaming(ref mut stream) => Pin::new(stream).poll_read(cx, buf),
         }
@@ -89,7 +93,10 @@ impl AsyncWrite for TlsStream {
                     pin.state = State::Streaming(stream);
                     result
                 }
-                Err(err) => Poll::Ready(Err(err)),
+                Err(err) => {
+                    metrics::count_tls_handshake_failure();
+                    Poll::Ready(Err(err))
+                }
             },
             State::Streaming(ref mut stream) => Pin::new(stream).poll_write(cx, buf),
         }
@@ -1,12 +1,19 @@
+use crate::service::access_log::BoxError;
+use http_body::Body;
 use hyper::client::HttpConnector;
 use hyper::Client;
 use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
 
-pub fn make_http_or_https_client() -> Client<HttpsConnector<HttpConnector>> {
+pub fn make_http_or_https_client<B>() -> Client<HttpsConnector<HttpConnector>, B>
+where
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
     let https = HttpsConnectorBuilder::new()
         .with_native_roots()
         .https_or_http()
         .enable_http1()
         .build();
-    Client::builder().build::<_, hyper::Body>(https)
+    Client::builder().build::<_, B>(https)
 }
@@ -0,0 +1,253 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_context.rs"]
+mod test_context;
+
+use http::{HeaderMap, HeaderValue};
+use std::fmt;
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+const B3: &str = "b3";
+const X_B3_TRACE_ID: &str = "x-b3-traceid";
+const X_B3_SPAN_ID: &str = "x-b3-spanid";
+const X_B3_PARENT_SPAN_ID: &str = "x-b3-parentspanid";
+const X_B3_SAMPLED: &str = "x-b3-sampled";
+const X_B3_FLAGS: &str = "x-b3-flags";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId(pub [u8; 16]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanId(pub [u8; 8]);
+
+impl TraceId {
+    pub fn random() -> Self {
+        let mut id = [0u8; 16];
+        while id == [0u8; 16] {
+            getrandom::getrandom(&mut id).expect("no random source");
+        }
+        TraceId(id)
+    }
+
+    // 64 bit ids of B3 are padded with zeros
+    fn parse(s: &str) -> Option<Self> {
+        let mut id = [0u8; 16];
+        match s.len() {
+            32 => decode_hex(s, &mut id)?,
+            16 => decode_hex(s, &mut id[8..])?,
+            _ => return None,
+        }
+        if id == [0u8; 16] {
+            return None;
+        }
+        Some(TraceId(id))
+    }
+
+    /// lower 64 bits, used for the sampling decision
+    pub fn low_u64(&self) -> u64 {
+        let mut low = [0u8; 8];
+        low.copy_from_slice(&self.0[8..]);
+        u64::from_be_bytes(low)
+    }
+}
+
+impl SpanId {
+    pub fn random() -> Self {
+        let mut id = [0u8; 8];
+        while id == [0u8; 8] {
+            getrandom::getrandom(&mut id).expect("no random source");
+        }
+        SpanId(id)
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let mut id = [0u8; 8];
+        if s.len() != 16 {
+            return None;
+        }
+        decode_hex(s, &mut id)?;
+        if id == [0u8; 8] {
+            return None;
+        }
+        Some(SpanId(id))
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+impl fmt::Display for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    for byte in bytes {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+fn decode_hex(s: &str, out: &mut [u8]) -> Option<()> {
+    if s.len() != out.len() * 2 || !s.is_ascii() {
+        return None;
+    }
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(())
+}
+
+/// header format of the incoming trace context, answered in the same format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagation {
+    W3c,
+    B3Single,
+    B3Multi,
+}
+
+/// context of the remote parent span
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: TraceId,
+    pub span_id: SpanId,
+    /// None: the parent didn't decide
+    pub sampled: Option<bool>,
+    pub trace_state: String,
+    pub propagation: Propagation,
+}
+
+fn get_header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+}
+
+/// trace context of the request (W3C Trace Context first, then B3)
+pub fn extract(headers: &HeaderMap) -> Option<TraceContext> {
+    extract_w3c(headers)
+        .or_else(|| extract_b3_single(headers))
+        .or_else(|| extract_b3_multi(headers))
+}
+
+// traceparent: 00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01
+fn extract_w3c(headers: &HeaderMap) -> Option<TraceContext> {
+    let value = get_header(headers, TRACEPARENT)?;
+    let parts: Vec<&str> = value.split('-').collect();
+    if parts.len() < 4 || parts[0].len() != 2 || parts[3].len() != 2 {
+        return None;
+    }
+    let version = u8::from_str_radix(parts[0], 16).ok()?;
+    // version 00 has exactly 4 parts, the later versions may have more
+    if version == 0xff || (version == 0 && parts.len() != 4) {
+        return None;
+    }
+    if parts[1].len() != 32 {
+        return None;
+    }
+    let flags = u8::from_str_radix(parts[3], 16).ok()?;
+
+    Some(TraceContext {
+        trace_id: TraceId::parse(parts[1])?,
+        span_id: SpanId::parse(parts[2])?,
+        sampled: Some(flags & 0x01 == 0x01),
+        trace_state: get_header(headers, TRACESTATE)
+            .unwrap_or_default()
+            .to_string(),
+        propagation: Propagation::W3c,
+    })
+}
+
+// b3: {trace id}-{span id}-{sampling}-{parent span id}
+fn extract_b3_single(headers: &HeaderMap) -> Option<TraceContext> {
+    let value = get_header(headers, B3)?;
+    let parts: Vec<&str> = value.split('-').collect();
+    if parts.len() < 2 {
+        return None;
+    }
+    Some(TraceContext {
+        trace_id: TraceId::parse(parts[0])?,
+        span_id: SpanId::parse(parts[1])?,
+        sampled: parts.get(2).and_then(|sampling| parse_b3_sampled(sampling)),
+        trace_state: String::new(),
+        propagation: Propagation::B3Single,
+    })
+}
+
+fn extract_b3_multi(headers: &HeaderMap) -> Option<TraceContext> {
+    let sampled = if get_header(headers, X_B3_FLAGS) == Some("1") {
+        // debug
+        Some(true)
+    } else {
+        get_header(headers, X_B3_SAMPLED).and_then(parse_b3_sampled)
+    };
+    Some(TraceContext {
+        trace_id: TraceId::parse(get_header(headers, X_B3_TRACE_ID)?)?,
+        span_id: SpanId::parse(get_header(headers, X_B3_SPAN_ID)?)?,
+        sampled,
+        trace_state: String::new(),
+        propagation: Propagation::B3Multi,
+    })
+}
+
+fn parse_b3_sampled(value: &str) -> Option<bool> {
+    match value {
+        "1" | "d" | "true" => Some(true),
+        "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// replace the trace headers of the request to the upstream with the span of the gateway
+pub fn inject(
+    headers: &mut HeaderMap,
+    trace_id: TraceId,
+    span_id: SpanId,
+    sampled: bool,
+    trace_state: &str,
+    propagation: Propagation,
+) {
+    for name in [
+        TRACEPARENT,
+        TRACESTATE,
+        B3,
+        X_B3_TRACE_ID,
+        X_B3_SPAN_ID,
+        X_B3_PARENT_SPAN_ID,
+        X_B3_SAMPLED,
+        X_B3_FLAGS,
+    ] {
+        headers.remove(name);
+    }
+
+    let flag = if sampled { "1" } else { "0" };
+    // always valid header values: hex digits
+    let header = |value: String| HeaderValue::from_str(&value).unwrap();
+
+    headers.insert(
+        TRACEPARENT,
+        header(format!("00-{}-{}-0{}", trace_id, span_id, flag)),
+    );
+    if !trace_state.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(trace_state) {
+            headers.insert(TRACESTATE, value);
+        }
+    }
+    match propagation {
+        Propagation::W3c => {}
+        Propagation::B3Single => {
+            headers.insert(B3, header(format!("{}-{}-{}", trace_id, span_id, flag)));
+        }
+        Propagation::B3Multi => {
+            headers.insert(X_B3_TRACE_ID, header(trace_id.to_string()));
+            headers.insert(X_B3_SPAN_ID, header(span_id.to_string()));
+            headers.insert(X_B3_SAMPLED, HeaderValue::from_static(flag));
+        }
+    }
+}
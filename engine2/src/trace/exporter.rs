@@ -0,0 +1,175 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+#[path = "test_exporter.rs"]
+mod test_exporter;
+
+use super::{AttributeValue, Span, SpanKind, TraceConfig};
+use crate::monitor::metrics;
+use crate::tls::tls_connector::make_http_or_https_client;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request};
+use hyper_rustls::HttpsConnector;
+use lazy_static::lazy_static;
+use log::warn;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio::time;
+
+// spans waiting for the exporter, the others are dropped
+const QUEUE_SIZE: usize = 2048;
+const BATCH_SIZE: usize = 512;
+const BATCH_INTERVAL: Duration = Duration::from_secs(5);
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const SCOPE_NAME: &str = "osori";
+
+lazy_static! {
+    static ref SPAN_SENDER: RwLock<Option<Sender<Span>>> = RwLock::new(None);
+}
+
+// spans dropped because the collector was too slow or unavailable
+static DROPPED_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// queue the span (never blocks the request)
+pub fn export(span: Span) {
+    if let Some(sender) = SPAN_SENDER.read().unwrap().as_ref() {
+        if let Err(TrySendError::Full(_)) = sender.try_send(span) {
+            DROPPED_COUNT.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// start to send the spans to the collector in batches
+pub fn handle(config: TraceConfig) {
+    let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+    *SPAN_SENDER.write().unwrap() = Some(sender);
+    tokio::spawn(run(config, receiver));
+}
+
+async fn run(config: TraceConfig, mut receiver: Receiver<Span>) {
+    let url = format!("{}/v1/traces", config.endpoint.trim_end_matches('/'));
+    let client = make_http_or_https_client::<Body>();
+
+    let mut closed = false;
+    while !closed {
+        let mut batch = Vec::new();
+        let deadline = time::sleep(BATCH_INTERVAL);
+        tokio::pin!(deadline);
+        while batch.len() < BATCH_SIZE {
+            tokio::select! {
+                span = receiver.recv() => match span {
+                    Some(span) => batch.push(span),
+                    None => {
+                        closed = true;
+                        break;
+                    }
+                },
+                _ = &mut deadline => break,
+            }
+        }
+        if batch.is_empty() {
+            continue;
+        }
+
+        let body = make_request_body(&config.service_name, &batch);
+        let result = match time::timeout(EXPORT_TIMEOUT, send(&client, &url, body)).await {
+            Ok(result) => result,
+            Err(_) => Err(String::from("timed out")),
+        };
+        let mut dropped = DROPPED_COUNT.swap(0, Ordering::Relaxed);
+        if let Err(e) = result {
+            warn!("failed to export {} spans: {}", batch.len(), e);
+            dropped += batch.len();
+        }
+        if dropped > 0 {
+            metrics::count_dropped_spans(dropped);
+            warn!("{} spans are dropped", dropped);
+        }
+    }
+}
+
+async fn send(
+    client: &Client<HttpsConnector<HttpConnector>, Body>,
+    url: &str,
+    body: String,
+) -> Result<(), String> {
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(url)
+        .header("content-type", "application/json")
+        .body(Body::from(body))
+        .map_err(|e| e.to_string())?;
+
+    let resp = client.request(req).await.map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("collector responded {}", resp.status()));
+    }
+    Ok(())
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn make_attribute(key: &str, value: &AttributeValue) -> Value {
+    let value = match value {
+        AttributeValue::String(value) => json!({ "stringValue": value }),
+        // int64 is a string in OTLP/JSON
+        AttributeValue::Int(value) => json!({ "intValue": value.to_string() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn make_span(span: &Span) -> Value {
+    let mut value = json!({
+        "traceId": span.trace_id.to_string(),
+        "spanId": span.span_id.to_string(),
+        "name": span.name,
+        // SPAN_KIND_SERVER, SPAN_KIND_CLIENT
+        "kind": match span.kind {
+            SpanKind::Server => 2,
+            SpanKind::Client => 3,
+        },
+        "startTimeUnixNano": unix_nanos(span.start),
+        "endTimeUnixNano": unix_nanos(span.end),
+        "attributes": span
+            .attributes
+            .iter()
+            .map(|(key, value)| make_attribute(key, value))
+            .collect::<Vec<Value>>(),
+        // STATUS_CODE_UNSET, STATUS_CODE_ERROR
+        "status": match &span.error {
+            Some(message) => json!({ "code": 2, "message": message }),
+            None => json!({ "code": 0 }),
+        },
+    });
+    if let Some(parent_span_id) = span.parent_span_id {
+        value["parentSpanId"] = json!(parent_span_id.to_string());
+    }
+    if !span.trace_state.is_empty() {
+        value["traceState"] = json!(span.trace_state);
+    }
+    value
+}
+
+/// ExportTraceServiceRequest of OTLP/JSON
+pub fn make_request_body(service_name: &str, spans: &[Span]) -> String {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [make_attribute("service.name", &AttributeValue::from(service_name))],
+            },
+            "scopeSpans": [{
+                "scope": { "name": SCOPE_NAME },
+                "spans": spans.iter().map(make_span).collect::<Vec<Value>>(),
+            }],
+        }],
+    })
+    .to_string()
+}
@@ -0,0 +1,133 @@
+pub mod context;
+pub mod exporter;
+
+use context::{Propagation, SpanId, TraceContext, TraceId};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::SystemTime;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// f64 bits
+static SAMPLE_RATIO: AtomicU64 = AtomicU64::new(0);
+
+/// where and how many spans are exported
+#[derive(Debug, Clone)]
+pub struct TraceConfig {
+    /// base url of the OTLP/HTTP collector (ex. http://127.0.0.1:4318)
+    pub endpoint: String,
+    /// 0.0 ~ 1.0 of the new traces, the decision of the parent is followed
+    pub sample_ratio: f64,
+    pub service_name: String,
+}
+
+/// start to export spans, tracing is disabled without the config
+pub fn handle(config: Option<TraceConfig>) {
+    if let Some(config) = config {
+        SAMPLE_RATIO.store(config.sample_ratio.to_bits(), Ordering::Relaxed);
+        exporter::handle(config);
+        ENABLED.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// parent based sampling: the decision of the parent, or the ratio by the trace id
+pub fn should_sample(parent: Option<&TraceContext>, trace_id: TraceId, ratio: f64) -> bool {
+    if let Some(sampled) = parent.and_then(|parent| parent.sampled) {
+        return sampled;
+    }
+    if ratio >= 1.0 {
+        return true;
+    }
+    if ratio <= 0.0 {
+        return false;
+    }
+    (trace_id.low_u64() as f64) < ratio * u64::MAX as f64
+}
+
+pub fn get_sample_ratio() -> f64 {
+    f64::from_bits(SAMPLE_RATIO.load(Ordering::Relaxed))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    Server,
+    Client,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::String(value)
+    }
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        AttributeValue::String(value.to_string())
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(value: i64) -> Self {
+        AttributeValue::Int(value)
+    }
+}
+
+/// span of the gateway, shared with the inner layers through request extensions
+#[derive(Debug, Clone)]
+pub struct SpanContext {
+    pub trace_id: TraceId,
+    pub span_id: SpanId,
+    pub sampled: bool,
+    pub trace_state: String,
+    pub propagation: Propagation,
+}
+
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub trace_id: TraceId,
+    pub span_id: SpanId,
+    pub parent_span_id: Option<SpanId>,
+    pub trace_state: String,
+    pub name: String,
+    pub kind: SpanKind,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(&'static str, AttributeValue)>,
+    /// None: ok
+    pub error: Option<String>,
+}
+
+impl Span {
+    pub fn start(name: String, kind: SpanKind, context: &SpanContext) -> Self {
+        Span {
+            trace_id: context.trace_id,
+            span_id: context.span_id,
+            parent_span_id: None,
+            trace_state: context.trace_state.clone(),
+            name,
+            kind,
+            start: SystemTime::now(),
+            end: SystemTime::now(),
+            attributes: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn set_attribute(&mut self, key: &'static str, value: impl Into<AttributeValue>) {
+        self.attributes.push((key, value.into()));
+    }
+
+    /// finish the span and queue it to the exporter
+    pub fn end(mut self) {
+        self.end = SystemTime::now();
+        exporter::export(self);
+    }
+}
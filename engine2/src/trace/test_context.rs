@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod test_context {
+    use super::super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_extract_w3c() {
+        let context = extract(&headers(&[
+            (
+                TRACEPARENT,
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            ),
+            (TRACESTATE, "congo=t61rcWkgMzE"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            context.trace_id.to_string(),
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+        assert_eq!(context.span_id.to_string(), "b7ad6b7169203331");
+        assert_eq!(context.sampled, Some(true));
+        assert_eq!(context.trace_state, "congo=t61rcWkgMzE");
+        assert_eq!(context.propagation, Propagation::W3c);
+
+        for invalid in [
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+            "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-extra",
+            "00-0af7651916cd43dd-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319z-b7ad6b7169203331-01",
+        ] {
+            let mut map = HeaderMap::new();
+            map.insert(TRACEPARENT, HeaderValue::from_static(invalid));
+            assert_eq!(extract(&map), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_extract_b3() {
+        let context = extract(&headers(&[(
+            B3,
+            "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-0-05e3ac9a4f6e3b90",
+        )]))
+        .unwrap();
+        assert_eq!(context.sampled, Some(false));
+        assert_eq!(context.propagation, Propagation::B3Single);
+
+        // 64 bit trace id
+        let context = extract(&headers(&[
+            (X_B3_TRACE_ID, "64fe8b2a57d3eff7"),
+            (X_B3_SPAN_ID, "e457b5a2e4d86bd1"),
+            (X_B3_FLAGS, "1"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            context.trace_id.to_string(),
+            "000000000000000064fe8b2a57d3eff7"
+        );
+        assert_eq!(context.sampled, Some(true));
+        assert_eq!(context.propagation, Propagation::B3Multi);
+    }
+
+    #[test]
+    fn test_inject() {
+        let mut map = headers(&[(X_B3_SPAN_ID, "e457b5a2e4d86bd1"), (X_B3_SAMPLED, "1")]);
+        let trace_id = TraceId::parse("0af7651916cd43dd8448eb211c80319c").unwrap();
+        let span_id = SpanId::parse("00f067aa0ba902b7").unwrap();
+
+        inject(
+            &mut map,
+            trace_id,
+            span_id,
+            false,
+            "",
+            Propagation::B3Single,
+        );
+        assert_eq!(
+            map[TRACEPARENT],
+            "00-0af7651916cd43dd8448eb211c80319c-00f067aa0ba902b7-00"
+        );
+        assert_eq!(
+            map[B3],
+            "0af7651916cd43dd8448eb211c80319c-00f067aa0ba902b7-0"
+        );
+        assert!(!map.contains_key(X_B3_SPAN_ID));
+        assert!(!map.contains_key(TRACESTATE));
+
+        let context = extract(&map).unwrap();
+        assert_eq!(context.span_id, span_id);
+        assert_eq!(context.sampled, Some(false));
+    }
+}
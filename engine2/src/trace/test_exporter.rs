@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod test_exporter {
+    use super::super::super::context::{Propagation, SpanId, TraceId};
+    use super::super::super::{should_sample, SpanContext};
+    use super::super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{body, Response, Server};
+    use std::convert::Infallible;
+
+    fn make_span() -> Span {
+        let context = SpanContext {
+            trace_id: TraceId([1; 16]),
+            span_id: SpanId([2; 8]),
+            sampled: true,
+            trace_state: String::from("a=b"),
+            propagation: Propagation::W3c,
+        };
+        let mut span = Span::start(String::from("GET /v1/test"), SpanKind::Server, &context);
+        span.parent_span_id = Some(SpanId([3; 8]));
+        span.set_attribute("http.method", "GET");
+        span.set_attribute("http.status_code", 502);
+        span.error = Some(String::from("status 502"));
+        span.end = span.start + Duration::from_millis(5);
+        span
+    }
+
+    #[test]
+    fn test_should_sample() {
+        let trace_id = TraceId([0xff; 16]);
+        assert!(should_sample(None, trace_id, 1.0));
+        assert!(!should_sample(None, trace_id, 0.5));
+        assert!(should_sample(None, TraceId([0; 16]), 0.5));
+        assert!(!should_sample(None, TraceId([0; 16]), 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_export_to_collector() {
+        // stand-in of OTLP/HTTP collector
+        let (body_sender, mut body_receiver) = mpsc::channel::<(String, Value)>(1);
+        let make_service = make_service_fn(move |_| {
+            let body_sender = body_sender.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let body_sender = body_sender.clone();
+                    async move {
+                        let path = req.uri().path().to_string();
+                        let bytes = body::to_bytes(req.into_body()).await.unwrap();
+                        let _ = body_sender
+                            .send((path, serde_json::from_slice(&bytes).unwrap()))
+                            .await;
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let endpoint = format!("http://{}/", server.local_addr());
+        tokio::spawn(server);
+
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(run(
+            TraceConfig {
+                endpoint,
+                sample_ratio: 1.0,
+                service_name: String::from("osori-test"),
+            },
+            receiver,
+        ));
+        sender.send(make_span()).await.unwrap();
+
+        let (path, body) = time::timeout(BATCH_INTERVAL * 2, body_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(path, "/v1/traces");
+
+        let resource_spans = &body["resourceSpans"][0];
+        assert_eq!(
+            resource_spans["resource"]["attributes"][0]["value"]["stringValue"],
+            "osori-test"
+        );
+        let span = &resource_spans["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["traceId"], "01010101010101010101010101010101");
+        assert_eq!(span["spanId"], "0202020202020202");
+        assert_eq!(span["parentSpanId"], "0303030303030303");
+        assert_eq!(span["traceState"], "a=b");
+        assert_eq!(span["kind"], 2);
+        assert_eq!(span["attributes"][1]["value"]["intValue"], "502");
+        assert_eq!(span["status"]["code"], 2);
+        let start: u128 = span["startTimeUnixNano"].as_str().unwrap().parse().unwrap();
+        let end: u128 = span["endTimeUnixNano"].as_str().unwrap().parse().unwrap();
+        assert_eq!(end - start, 5_000_000);
+    }
+}